@@ -7,7 +7,7 @@ use tokio::{
     runtime::Runtime,
     sync::oneshot::{self, Sender},
 };
-use warp::serve;
+use warp::{log, path, serve, Filter, Reply};
 
 mod handlers;
 mod routes;
@@ -25,23 +25,38 @@ impl fmt::Display for ApiServerError {
 
 impl Error for ApiServerError {}
 
+/// Serves the API of the first state at the root and the one of a second state under `/second`.
 pub fn run(
-    state: State,
+    states: &[State],
     socket: SocketAddr,
     runtime: &Runtime,
 ) -> Result<Sender<()>, ApiServerError> {
-    runtime.block_on(start_api(state, socket, runtime))
+    runtime.block_on(start_api(states, socket, runtime))
 }
 
 // needs to run in a tokio runtime
+#[allow(clippy::unused_async)]
 async fn start_api(
-    state: State,
+    states: &[State],
     socket: SocketAddr,
     runtime: &Runtime,
 ) -> Result<Sender<()>, ApiServerError> {
     let (tx, rx) = oneshot::channel();
 
-    let (_, server) = serve(routes::get(state))
+    let mut routes = routes::get(states[0].clone())
+        .map(Reply::into_response)
+        .boxed();
+
+    if let Some(second) = states.get(1) {
+        routes = routes
+            .or(path("second")
+                .and(routes::get(second.clone()))
+                .map(Reply::into_response))
+            .unify()
+            .boxed();
+    }
+
+    let (_, server) = serve(routes.with(log("access-log")))
         .try_bind_with_graceful_shutdown(socket, async move {
             info!("Starting API on {socket}");
             rx.await.ok();
@@ -1,13 +1,26 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::Infallible;
 use std::fmt;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
+use error_stack::Report;
+use image::ImageFormat;
+use serde::{Deserialize, Serialize};
 use strum::VariantNames;
 use strum_macros::EnumString;
+use warp::http::StatusCode;
+use warp::hyper::body::Bytes;
+use warp::reply::{self, Response};
 use warp::Reply;
 
+use crate::effects::custom::{Program, Source};
+use crate::effects::script;
+use crate::effects::sequence::{Layout, Playback, Sequence};
+use crate::pixel::Color;
+use crate::playlist::{Entry, Player, Playlist};
+use crate::preset::Preset;
 use crate::state::{Mode, State};
+use crate::storage::{self, Storage, StorageError, PLAYLISTS, PRESETS, SEQUENCES};
 
 #[derive(Debug, EnumString)]
 #[strum(ascii_case_insensitive)]
@@ -15,6 +28,8 @@ pub enum HSVComponent {
     H,
     S,
     V,
+    /// White channel of RGBW strips.
+    W,
 }
 
 impl fmt::Display for HSVComponent {
@@ -23,6 +38,7 @@ impl fmt::Display for HSVComponent {
             Self::H => write!(f, "HUE"),
             Self::S => write!(f, "SAT"),
             Self::V => write!(f, "VAL"),
+            Self::W => write!(f, "WHITE"),
         }
     }
 }
@@ -33,7 +49,9 @@ pub enum PlainTarget {
     H,
     S,
     V,
+    W,
     Mode,
+    Remaining,
 }
 
 #[allow(clippy::unused_async)]
@@ -53,26 +71,17 @@ pub async fn static_all_modes() -> Result<impl Reply, Infallible> {
 }
 
 pub async fn get_mode(state: State) -> Result<impl Reply, Infallible> {
-    let safe_state = state.lock().await;
+    let mode = state.lock().await.mode;
 
-    Ok(format!("Current mode: {}", safe_state.mode))
+    Ok(format!("Current mode: {mode}"))
 }
 
 pub async fn set_mode(new_mode: Mode, state: State) -> Result<String, Infallible> {
-    let mut safe_state = state.lock().await;
-
-    safe_state.interval = match new_mode {
-        Mode::ALARM => Duration::from_millis(1000),
-        Mode::COLORRAPE => Duration::from_millis(500 + (9500.0 * (safe_state.hue / 360.0)) as u64),
-        Mode::STROBE => Duration::from_millis(50 + (950.0 * (safe_state.hue / 360.0)) as u64),
-        _ => Duration::from_millis(300_000),
-    };
-
-    safe_state.render = true;
-    safe_state.start = Instant::now();
-    safe_state.mode = new_mode;
-
-    drop(safe_state);
+    let mut state = state.lock().await;
+    // choosing a mode manually takes over from a running playlist
+    state.playlist = None;
+    state.set_mode(new_mode);
+    drop(state);
 
     Ok(format!("Updated mode: {new_mode}"))
 }
@@ -86,6 +95,18 @@ pub async fn set_mode_int(new_mode: u8, state: State) -> Result<impl Reply, Infa
     }
 }
 
+pub async fn get_remaining(state: State) -> Result<impl Reply, Infallible> {
+    let safe_state = state.lock().await;
+    let mode = safe_state.mode;
+    let remaining = safe_state.remaining();
+    drop(safe_state);
+
+    Ok(remaining.map_or_else(
+        || format!("Mode {mode} is not timed"),
+        |left| format!("Remaining time of {mode}: {}s", left.as_secs()),
+    ))
+}
+
 pub async fn get_component(
     component: HSVComponent,
     state: State,
@@ -96,6 +117,7 @@ pub async fn get_component(
         HSVComponent::H => safe_state.hue,
         HSVComponent::S => safe_state.sat,
         HSVComponent::V => safe_state.val,
+        HSVComponent::W => safe_state.white,
     };
 
     drop(safe_state);
@@ -115,18 +137,7 @@ pub async fn set_component(
             safe_state.hue = ((value % 360.0) + 360.0) % 360.0;
 
             // update interval when depending on hue value
-            let current_mode = safe_state.mode;
-            match current_mode {
-                Mode::COLORRAPE => {
-                    safe_state.interval =
-                        Duration::from_millis(500 + (9500.0 * (safe_state.hue / 360.0)) as u64);
-                }
-                Mode::STROBE => {
-                    safe_state.interval =
-                        Duration::from_millis(50 + (950.0 * (safe_state.hue / 360.0)) as u64);
-                }
-                _ => {}
-            };
+            safe_state.update_interval();
 
             safe_state.hue
         }
@@ -138,6 +149,10 @@ pub async fn set_component(
             safe_state.val = value.clamp(0.0, 1.0);
             safe_state.val
         }
+        HSVComponent::W => {
+            safe_state.white = value.clamp(0.0, 1.0);
+            safe_state.white
+        }
     };
 
     safe_state.render = true;
@@ -155,7 +170,7 @@ pub async fn set_component_int(
 ) -> Result<impl Reply, Infallible> {
     let result = match component {
         HSVComponent::H => (((value % 360) + 360) % 360) as f32,
-        HSVComponent::S | HSVComponent::V => value.clamp(0, 255) as f32 / 255.0,
+        HSVComponent::S | HSVComponent::V | HSVComponent::W => value.clamp(0, 255) as f32 / 255.0,
     };
 
     set_component(component, result, state).await
@@ -168,6 +183,567 @@ pub async fn get_plain(target: PlainTarget, state: State) -> Result<impl Reply,
         PlainTarget::H => Ok(safe_state.hue.to_string()),
         PlainTarget::S => Ok(safe_state.sat.to_string()),
         PlainTarget::V => Ok(safe_state.val.to_string()),
+        PlainTarget::W => Ok(safe_state.white.to_string()),
         PlainTarget::Mode => Ok(safe_state.mode.to_string().to_lowercase()),
+        PlainTarget::Remaining => Ok(safe_state
+            .remaining()
+            .map_or_else(|| "none".to_owned(), |left| left.as_secs().to_string())),
     }
 }
+
+pub async fn get_params(state: State) -> Result<impl Reply, Infallible> {
+    let params = state.lock().await.params.clone();
+
+    Ok(reply::json(&params))
+}
+
+pub async fn set_params(
+    effect: String,
+    values: HashMap<String, String>,
+    state: State,
+) -> Result<Response, Infallible> {
+    let mut safe_state = state.lock().await;
+
+    let result = if values.is_empty() {
+        safe_state.params.get(&effect)
+    } else {
+        safe_state.render = true;
+        safe_state.params.set(&effect, &values)
+    };
+
+    drop(safe_state);
+
+    match result {
+        Ok(params) => Ok(reply::json(&params).into_response()),
+        Err(err) => {
+            Ok(reply::with_status(err.to_string(), StatusCode::BAD_REQUEST).into_response())
+        }
+    }
+}
+
+pub async fn get_custom(state: State) -> Result<Response, Infallible> {
+    let source = state
+        .lock()
+        .await
+        .custom
+        .as_ref()
+        .map(|program| program.source().clone());
+
+    Ok(source.map_or_else(
+        || reply::with_status("No custom expressions set", StatusCode::NOT_FOUND).into_response(),
+        |source| reply::json(&source).into_response(),
+    ))
+}
+
+pub async fn set_custom(source: Source, state: State) -> Result<Response, Infallible> {
+    // compiling may take a moment, so don't hold the lock while doing it
+    let program = match Program::compile(source) {
+        Ok(program) => program,
+        Err(err) => {
+            return Ok(reply::with_status(
+                format!("Invalid expression {err}"),
+                StatusCode::BAD_REQUEST,
+            )
+            .into_response())
+        }
+    };
+
+    let mut safe_state = state.lock().await;
+    safe_state.custom = Some(program);
+    safe_state.render = true;
+    drop(safe_state);
+
+    Ok("Updated custom expressions".into_response())
+}
+
+pub async fn get_scripts(state: State) -> Result<impl Reply, Infallible> {
+    let scripts = state.lock().await.scripts.clone();
+
+    Ok(reply::json(&scripts))
+}
+
+pub async fn reload_scripts(state: State) -> Result<impl Reply, Infallible> {
+    state.lock().await.reload_scripts = true;
+
+    Ok("Reloading scripts")
+}
+
+pub async fn set_script(name: String, body: Bytes, state: State) -> Result<Response, Infallible> {
+    if let Err(report) = Storage::check_name(&name) {
+        return Ok(storage_response(&report));
+    }
+
+    let Some(dir) = state.lock().await.script_dir.clone() else {
+        return Ok(
+            reply::with_status("No script directory configured", StatusCode::NOT_FOUND)
+                .into_response(),
+        );
+    };
+
+    // only store scripts that can be loaded
+    let checked = std::str::from_utf8(&body)
+        .map_err(|err| err.to_string())
+        .and_then(script::check);
+
+    if let Err(err) = checked {
+        return Ok(
+            reply::with_status(format!("Invalid script: {err}"), StatusCode::BAD_REQUEST)
+                .into_response(),
+        );
+    }
+
+    if let Err(report) = storage::write(&dir.join(format!("{name}.rhai")), &body) {
+        return Ok(storage_response(&report));
+    }
+
+    state.lock().await.reload_scripts = true;
+
+    Ok(format!("Saved script {name}").into_response())
+}
+
+#[derive(Deserialize)]
+pub struct FrameOptions {
+    /// Seconds without a new frame after which the previous mode is restored.
+    timeout: Option<f32>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FrameColor {
+    Hex(Color),
+    Rgb([u8; 3]),
+}
+
+impl From<FrameColor> for Color {
+    fn from(color: FrameColor) -> Self {
+        match color {
+            FrameColor::Hex(color) => color,
+            FrameColor::Rgb([r, g, b]) => Self {
+                r: r as f32 / 255.0,
+                g: g as f32 / 255.0,
+                b: b as f32 / 255.0,
+            },
+        }
+    }
+}
+
+// frames can be sent as JSON, as raw RGB bytes or as a string of hex colors
+fn parse_frame(content_type: Option<&str>, body: &[u8]) -> Result<Vec<Color>, String> {
+    let mime = content_type
+        .and_then(|value| value.split(';').next())
+        .map(str::trim);
+
+    match mime {
+        Some("application/json") => serde_json::from_slice::<Vec<FrameColor>>(body)
+            .map(|colors| colors.into_iter().map(Color::from).collect())
+            .map_err(|err| err.to_string()),
+        Some("application/octet-stream") => {
+            if !body.len().is_multiple_of(3) {
+                return Err("raw frames need 3 bytes per LED".to_owned());
+            }
+
+            Ok(body
+                .chunks_exact(3)
+                .map(|rgb| Color {
+                    r: rgb[0] as f32 / 255.0,
+                    g: rgb[1] as f32 / 255.0,
+                    b: rgb[2] as f32 / 255.0,
+                })
+                .collect())
+        }
+        _ => {
+            let text = std::str::from_utf8(body).map_err(|err| err.to_string())?;
+            let hex: String = text
+                .chars()
+                .filter(|c| !c.is_whitespace() && *c != ',' && *c != '#')
+                .collect();
+
+            if !hex.len().is_multiple_of(6) || !hex.is_ascii() {
+                return Err("hex frames need 6 hex digits per LED".to_owned());
+            }
+
+            (0..hex.len())
+                .step_by(6)
+                .map(|i| hex[i..i + 6].parse())
+                .collect()
+        }
+    }
+}
+
+pub async fn get_frame(state: State) -> Result<impl Reply, Infallible> {
+    let frame = state.lock().await.direct.clone();
+
+    Ok(reply::json(&frame))
+}
+
+pub async fn set_frame(
+    start: usize,
+    options: FrameOptions,
+    content_type: Option<String>,
+    body: Bytes,
+    state: State,
+) -> Result<Response, Infallible> {
+    let timeout = match options.timeout.map(Duration::try_from_secs_f32) {
+        None => None,
+        Some(Ok(timeout)) => Some(timeout),
+        Some(Err(err)) => {
+            return Ok(reply::with_status(
+                format!("Invalid timeout: {err}"),
+                StatusCode::BAD_REQUEST,
+            )
+            .into_response())
+        }
+    };
+
+    let colors = match parse_frame(content_type.as_deref(), &body) {
+        Ok(colors) => colors,
+        Err(err) => {
+            return Ok(
+                reply::with_status(format!("Invalid frame: {err}"), StatusCode::BAD_REQUEST)
+                    .into_response(),
+            )
+        }
+    };
+
+    let written = state.lock().await.write_direct(start, &colors, timeout);
+
+    if written == 0 {
+        return Ok(reply::with_status(
+            "Invalid frame: no LEDs to update".to_owned(),
+            StatusCode::BAD_REQUEST,
+        )
+        .into_response());
+    }
+
+    Ok(format!("Updated {written} LEDs").into_response())
+}
+
+fn storage_response(report: &Report<StorageError>) -> Response {
+    let status = match report.current_context() {
+        StorageError::InvalidName => StatusCode::BAD_REQUEST,
+        StorageError::NotFound => StatusCode::NOT_FOUND,
+        StorageError::Io => {
+            warn!("{report:?}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    };
+
+    reply::with_status(report.current_context().to_string(), status).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct SequenceOptions {
+    /// Duration of every frame in seconds for CSV uploads.
+    #[serde(default = "default_frame_duration")]
+    duration: f32,
+    #[serde(default)]
+    playback: Playback,
+    /// Whether rows or columns of uploaded images become frames.
+    #[serde(default)]
+    layout: Layout,
+}
+
+const fn default_frame_duration() -> f32 {
+    0.1
+}
+
+pub async fn get_sequences(state: State) -> Result<Response, Infallible> {
+    let storage = state.lock().await.storage.clone();
+
+    Ok(match storage.list(SEQUENCES) {
+        Ok(names) => reply::json(&names).into_response(),
+        Err(report) => storage_response(&report),
+    })
+}
+
+pub async fn get_sequence(name: String, state: State) -> Result<Response, Infallible> {
+    let storage = state.lock().await.storage.clone();
+
+    Ok(match storage.load::<Sequence>(SEQUENCES, &name) {
+        Ok(sequence) => reply::json(&sequence).into_response(),
+        Err(report) => storage_response(&report),
+    })
+}
+
+// sequences can be sent as JSON, as PNG or GIF image or as CSV where every row is a frame
+pub async fn set_sequence(
+    name: String,
+    options: SequenceOptions,
+    content_type: Option<String>,
+    body: Bytes,
+    state: State,
+) -> Result<Response, Infallible> {
+    let mime = content_type
+        .as_deref()
+        .and_then(|value| value.split(';').next())
+        .map(str::trim);
+
+    let image_format = match mime {
+        Some("image/png") => Some(ImageFormat::Png),
+        Some("image/gif") => Some(ImageFormat::Gif),
+        _ => None,
+    };
+
+    let sequence = match (mime, image_format) {
+        (_, Some(format)) => {
+            let count = state.lock().await.count;
+
+            // decoding and scaling images takes a while, so keep it off the async workers
+            tokio::task::spawn_blocking(move || {
+                Sequence::from_image(
+                    &body,
+                    format,
+                    options.layout,
+                    options.duration,
+                    options.playback,
+                    count,
+                )
+            })
+            .await
+            .unwrap_or_else(|err| Err(err.to_string()))
+        }
+        (Some("application/json"), _) => {
+            serde_json::from_slice::<Sequence>(&body).map_err(|err| err.to_string())
+        }
+        _ => std::str::from_utf8(&body)
+            .map_err(|err| err.to_string())
+            .and_then(|text| Sequence::from_csv(text, options.duration, options.playback)),
+    };
+
+    let sequence = match sequence {
+        Ok(sequence) => sequence,
+        Err(err) => {
+            return Ok(reply::with_status(
+                format!("Invalid sequence: {err}"),
+                StatusCode::BAD_REQUEST,
+            )
+            .into_response())
+        }
+    };
+
+    let storage = state.lock().await.storage.clone();
+
+    if let Err(report) = storage.save(SEQUENCES, &name, &sequence) {
+        return Ok(storage_response(&report));
+    }
+
+    // update the sequence in case it is currently playing
+    let mut state = state.lock().await;
+    if let Some((playing, _)) = &state.sequence {
+        if *playing == name {
+            state.sequence = Some((name.clone(), sequence.clone()));
+            if state.mode == Mode::SEQUENCE {
+                state.set_mode(Mode::SEQUENCE);
+            }
+        }
+    }
+    drop(state);
+
+    Ok(format!(
+        "Saved sequence {name} with {} frames",
+        sequence.frames.len()
+    )
+    .into_response())
+}
+
+pub async fn delete_sequence(name: String, state: State) -> Result<Response, Infallible> {
+    let storage = state.lock().await.storage.clone();
+
+    Ok(match storage.delete(SEQUENCES, &name) {
+        Ok(()) => format!("Deleted sequence {name}").into_response(),
+        Err(report) => storage_response(&report),
+    })
+}
+
+pub async fn play_sequence(name: String, state: State) -> Result<Response, Infallible> {
+    let storage = state.lock().await.storage.clone();
+
+    let sequence = match storage.load::<Sequence>(SEQUENCES, &name) {
+        Ok(sequence) => sequence,
+        Err(report) => return Ok(storage_response(&report)),
+    };
+
+    let mut state = state.lock().await;
+    state.sequence = Some((name.clone(), sequence));
+    state.playlist = None;
+    state.set_mode(Mode::SEQUENCE);
+    drop(state);
+
+    Ok(format!("Playing sequence {name}").into_response())
+}
+
+pub async fn get_playlists(state: State) -> Result<Response, Infallible> {
+    let storage = state.lock().await.storage.clone();
+
+    Ok(match storage.list(PLAYLISTS) {
+        Ok(names) => reply::json(&names).into_response(),
+        Err(report) => storage_response(&report),
+    })
+}
+
+pub async fn get_playlist(name: String, state: State) -> Result<Response, Infallible> {
+    let storage = state.lock().await.storage.clone();
+
+    Ok(match storage.load::<Playlist>(PLAYLISTS, &name) {
+        Ok(playlist) => reply::json(&playlist).into_response(),
+        Err(report) => storage_response(&report),
+    })
+}
+
+pub async fn set_playlist(name: String, body: Bytes, state: State) -> Result<Response, Infallible> {
+    let playlist = match serde_json::from_slice::<Playlist>(&body) {
+        Ok(playlist) => playlist,
+        Err(err) => {
+            return Ok(reply::with_status(
+                format!("Invalid playlist: {err}"),
+                StatusCode::BAD_REQUEST,
+            )
+            .into_response())
+        }
+    };
+
+    let storage = state.lock().await.storage.clone();
+
+    Ok(match storage.save(PLAYLISTS, &name, &playlist) {
+        Ok(()) => format!(
+            "Saved playlist {name} with {} entries",
+            playlist.entries.len()
+        )
+        .into_response(),
+        Err(report) => storage_response(&report),
+    })
+}
+
+pub async fn delete_playlist(name: String, state: State) -> Result<Response, Infallible> {
+    let storage = state.lock().await.storage.clone();
+
+    Ok(match storage.delete(PLAYLISTS, &name) {
+        Ok(()) => format!("Deleted playlist {name}").into_response(),
+        Err(report) => storage_response(&report),
+    })
+}
+
+pub async fn play_playlist(name: String, state: State) -> Result<Response, Infallible> {
+    let storage = state.lock().await.storage.clone();
+
+    let playlist = match storage.load::<Playlist>(PLAYLISTS, &name) {
+        Ok(playlist) => playlist,
+        Err(report) => return Ok(storage_response(&report)),
+    };
+
+    let player = Player::new(name.clone(), playlist, &storage);
+    state.lock().await.play_playlist(player);
+
+    Ok(format!("Playing playlist {name}").into_response())
+}
+
+#[derive(Serialize)]
+struct PlaylistStatus {
+    name: String,
+    index: usize,
+    entry: Entry,
+    /// Seconds until the next entry is shown.
+    remaining: f32,
+}
+
+pub async fn get_playlist_status(state: State) -> Result<impl Reply, Infallible> {
+    let status = state
+        .lock()
+        .await
+        .playlist
+        .as_ref()
+        .map(|player| PlaylistStatus {
+            name: player.name.clone(),
+            index: player.index(),
+            entry: player.current().clone(),
+            remaining: player.remaining().as_secs_f32(),
+        });
+
+    Ok(reply::json(&status))
+}
+
+pub async fn next_playlist_entry(state: State) -> Result<impl Reply, Infallible> {
+    let mut state = state.lock().await;
+
+    if state.playlist.is_none() {
+        return Ok("No playlist running");
+    }
+
+    state.advance_playlist();
+    drop(state);
+
+    Ok("Switched to the next entry")
+}
+
+pub async fn stop_playlist(state: State) -> Result<impl Reply, Infallible> {
+    state.lock().await.playlist = None;
+
+    Ok("Stopped playlist")
+}
+
+pub async fn get_presets(state: State) -> Result<Response, Infallible> {
+    let storage = state.lock().await.storage.clone();
+
+    Ok(match storage.list(PRESETS) {
+        Ok(names) => reply::json(&names).into_response(),
+        Err(report) => storage_response(&report),
+    })
+}
+
+pub async fn get_preset(name: String, state: State) -> Result<Response, Infallible> {
+    let storage = state.lock().await.storage.clone();
+
+    Ok(match storage.load::<Preset>(PRESETS, &name) {
+        Ok(preset) => reply::json(&preset).into_response(),
+        Err(report) => storage_response(&report),
+    })
+}
+
+pub async fn save_preset(name: String, state: State) -> Result<Response, Infallible> {
+    let safe_state = state.lock().await;
+    let preset = Preset::capture(&safe_state);
+    let storage = safe_state.storage.clone();
+    drop(safe_state);
+
+    if preset.interval().is_none() {
+        return Ok(reply::with_status(
+            "Invalid preset: the interval needs to be at least 1 ms".to_owned(),
+            StatusCode::BAD_REQUEST,
+        )
+        .into_response());
+    }
+
+    Ok(match storage.save(PRESETS, &name, &preset) {
+        Ok(()) => format!("Saved preset {name}").into_response(),
+        Err(report) => storage_response(&report),
+    })
+}
+
+pub async fn delete_preset(name: String, state: State) -> Result<Response, Infallible> {
+    let storage = state.lock().await.storage.clone();
+
+    Ok(match storage.delete(PRESETS, &name) {
+        Ok(()) => format!("Deleted preset {name}").into_response(),
+        Err(report) => storage_response(&report),
+    })
+}
+
+pub async fn recall_preset(name: String, state: State) -> Result<Response, Infallible> {
+    let storage = state.lock().await.storage.clone();
+
+    let preset = match storage.load::<Preset>(PRESETS, &name) {
+        Ok(preset) => preset.load(&storage),
+        Err(report) => return Ok(storage_response(&report)),
+    };
+
+    let mut state = state.lock().await;
+    state.playlist = None;
+    preset.apply(&mut state);
+    drop(state);
+
+    Ok(format!("Recalled preset {name}").into_response())
+}
+
+pub async fn get_power(state: State) -> Result<impl Reply, Infallible> {
+    Ok(reply::json(&state.lock().await.power))
+}
@@ -1,5 +1,6 @@
+use std::collections::HashMap;
 use std::convert::Infallible;
-use warp::{any, log, path, Filter, Rejection, Reply};
+use warp::{any, body, header, path, query, Filter, Rejection, Reply};
 
 use crate::api::handlers::{self, HSVComponent, PlainTarget};
 use crate::state::{Mode, State};
@@ -9,8 +10,15 @@ pub fn get(state: State) -> impl Filter<Extract = (impl Reply,), Error = Rejecti
     static_routes()
         .or(mode_routes(state.clone()))
         .or(component_routes(state.clone()))
-        .or(plain_routes(state))
-        .with(log("access-log"))
+        .or(plain_routes(state.clone()))
+        .or(params_routes(state.clone()))
+        .or(custom_routes(state.clone()))
+        .or(script_routes(state.clone()))
+        .or(frame_routes(state.clone()))
+        .or(sequence_routes(state.clone()))
+        .or(playlist_routes(state.clone()))
+        .or(preset_routes(state.clone()))
+        .or(power_routes(state))
 }
 
 fn with_state(state: State) -> impl Filter<Extract = (State,), Error = Infallible> + Clone {
@@ -33,10 +41,13 @@ fn mode_routes(state: State) -> impl Filter<Extract = (impl Reply,), Error = Rej
         .and(with_state(state.clone()))
         .and_then(handlers::set_mode);
     let set_mode_int = path!("mode" / u8)
-        .and(with_state(state))
+        .and(with_state(state.clone()))
         .and_then(handlers::set_mode_int);
+    let get_remaining = path!("remaining")
+        .and(with_state(state))
+        .and_then(handlers::get_remaining);
 
-    get_mode.or(set_mode).or(set_mode_int)
+    get_mode.or(set_mode).or(set_mode_int).or(get_remaining)
 }
 
 fn component_routes(
@@ -60,3 +71,180 @@ fn plain_routes(state: State) -> impl Filter<Extract = (impl Reply,), Error = Re
         .and(with_state(state))
         .and_then(handlers::get_plain)
 }
+
+fn params_routes(state: State) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let get_params = path!("params")
+        .and(with_state(state.clone()))
+        .and_then(handlers::get_params);
+    let set_params = path!("params" / String)
+        .and(query::<HashMap<String, String>>())
+        .and(with_state(state))
+        .and_then(handlers::set_params);
+
+    get_params.or(set_params)
+}
+
+fn custom_routes(state: State) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let get_custom = path!("custom")
+        .and(warp::get())
+        .and(with_state(state.clone()))
+        .and_then(handlers::get_custom);
+    let set_custom = path!("custom")
+        .and(warp::post())
+        .and(body::content_length_limit(16 * 1024))
+        .and(body::json())
+        .and(with_state(state))
+        .and_then(handlers::set_custom);
+
+    get_custom.or(set_custom)
+}
+
+fn script_routes(state: State) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let get_scripts = path!("scripts")
+        .and(with_state(state.clone()))
+        .and_then(handlers::get_scripts);
+    let reload_scripts = path!("scripts" / "reload")
+        .and(with_state(state.clone()))
+        .and_then(handlers::reload_scripts);
+    let set_script = path!("scripts" / String)
+        .and(warp::post())
+        .and(body::content_length_limit(64 * 1024))
+        .and(body::bytes())
+        .and(with_state(state))
+        .and_then(handlers::set_script);
+
+    get_scripts.or(reload_scripts).or(set_script)
+}
+
+fn frame_routes(state: State) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let get_frame = path!("frame")
+        .and(warp::get())
+        .and(with_state(state.clone()))
+        .and_then(handlers::get_frame);
+
+    // writes without a start index begin at the first LED
+    let start = path!("frame").map(|| 0).or(path!("frame" / usize)).unify();
+    let set_frame = start
+        .and(warp::post())
+        .and(query::<handlers::FrameOptions>())
+        .and(header::optional::<String>("content-type"))
+        .and(body::content_length_limit(1024 * 1024))
+        .and(body::bytes())
+        .and(with_state(state))
+        .and_then(handlers::set_frame);
+
+    get_frame.or(set_frame)
+}
+
+fn sequence_routes(
+    state: State,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let get_sequences = path!("sequences")
+        .and(warp::get())
+        .and(with_state(state.clone()))
+        .and_then(handlers::get_sequences);
+    let get_sequence = path!("sequences" / String)
+        .and(warp::get())
+        .and(with_state(state.clone()))
+        .and_then(handlers::get_sequence);
+    let set_sequence = path!("sequences" / String)
+        .and(warp::post())
+        .and(query::<handlers::SequenceOptions>())
+        .and(header::optional::<String>("content-type"))
+        .and(body::content_length_limit(4 * 1024 * 1024))
+        .and(body::bytes())
+        .and(with_state(state.clone()))
+        .and_then(handlers::set_sequence);
+    let delete_sequence = path!("sequences" / String)
+        .and(warp::delete())
+        .and(with_state(state.clone()))
+        .and_then(handlers::delete_sequence);
+    let play_sequence = path!("sequences" / String / "play")
+        .and(with_state(state))
+        .and_then(handlers::play_sequence);
+
+    get_sequences
+        .or(get_sequence)
+        .or(set_sequence)
+        .or(delete_sequence)
+        .or(play_sequence)
+}
+
+fn playlist_routes(
+    state: State,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let get_playlists = path!("playlists")
+        .and(warp::get())
+        .and(with_state(state.clone()))
+        .and_then(handlers::get_playlists);
+    let get_playlist = path!("playlists" / String)
+        .and(warp::get())
+        .and(with_state(state.clone()))
+        .and_then(handlers::get_playlist);
+    let set_playlist = path!("playlists" / String)
+        .and(warp::post())
+        .and(body::content_length_limit(64 * 1024))
+        .and(body::bytes())
+        .and(with_state(state.clone()))
+        .and_then(handlers::set_playlist);
+    let delete_playlist = path!("playlists" / String)
+        .and(warp::delete())
+        .and(with_state(state.clone()))
+        .and_then(handlers::delete_playlist);
+    let play_playlist = path!("playlists" / String / "play")
+        .and(with_state(state.clone()))
+        .and_then(handlers::play_playlist);
+
+    let get_status = path!("playlist")
+        .and(with_state(state.clone()))
+        .and_then(handlers::get_playlist_status);
+    let next = path!("playlist" / "next")
+        .and(with_state(state.clone()))
+        .and_then(handlers::next_playlist_entry);
+    let stop = path!("playlist" / "stop")
+        .and(with_state(state))
+        .and_then(handlers::stop_playlist);
+
+    get_playlists
+        .or(get_playlist)
+        .or(set_playlist)
+        .or(delete_playlist)
+        .or(play_playlist)
+        .or(get_status)
+        .or(next)
+        .or(stop)
+}
+
+fn preset_routes(state: State) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let get_presets = path!("presets")
+        .and(warp::get())
+        .and(with_state(state.clone()))
+        .and_then(handlers::get_presets);
+    let get_preset = path!("presets" / String)
+        .and(warp::get())
+        .and(with_state(state.clone()))
+        .and_then(handlers::get_preset);
+    let save_preset = path!("presets" / String)
+        .and(warp::post())
+        .and(with_state(state.clone()))
+        .and_then(handlers::save_preset);
+    let delete_preset = path!("presets" / String)
+        .and(warp::delete())
+        .and(with_state(state.clone()))
+        .and_then(handlers::delete_preset);
+    let recall_preset = path!("presets" / String / "recall")
+        .and(with_state(state))
+        .and_then(handlers::recall_preset);
+
+    get_presets
+        .or(get_preset)
+        .or(save_preset)
+        .or(delete_preset)
+        .or(recall_preset)
+}
+
+fn power_routes(state: State) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    path!("power")
+        .and(with_state(state))
+        .and_then(handlers::get_power)
+}
@@ -0,0 +1,598 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use error_stack::{IntoReport, Report, Result, ResultExt};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::effects::{Curve, Params};
+use crate::layout::{Layout, Matrix, Segment, Wiring};
+use crate::persistence::Startup;
+use crate::pixel::{ColorOrder, Gamma, WhiteExtraction};
+use crate::power::Power;
+use crate::state::StateStruct;
+use crate::storage::{Storage, PRESETS};
+use crate::Cli;
+
+/// GPIO pins that can drive LEDs (PWM, PCM and SPI).
+const PINS: [i32; 13] = [10, 12, 13, 18, 19, 21, 31, 38, 40, 41, 45, 52, 53];
+/// GPIO pins of the first PWM channel.
+const PWM0_PINS: [i32; 4] = [12, 18, 40, 52];
+/// GPIO pins of the second PWM channel.
+const PWM1_PINS: [i32; 5] = [13, 19, 41, 45, 53];
+
+#[derive(Debug)]
+pub struct ConfigError;
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt.write_str("Config error: invalid configuration")
+    }
+}
+
+impl Error for ConfigError {}
+
+/// LED chips that can be driven by the ws281x library.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StripType {
+    Ws2811,
+    #[default]
+    Ws2812,
+    Ws2813,
+    Ws2815,
+    Sk6812,
+    Sk6812Rgbw,
+}
+
+impl StripType {
+    /// Returns the color order most strips of this type use.
+    pub const fn color_order(self) -> ColorOrder {
+        match self {
+            Self::Ws2811 => ColorOrder::RGB,
+            Self::Ws2812 | Self::Ws2813 | Self::Ws2815 | Self::Sk6812 => ColorOrder::GRB,
+            Self::Sk6812Rgbw => ColorOrder::GRBW,
+        }
+    }
+}
+
+/// Initial color and effect parameters.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Defaults {
+    pub hue: Option<f32>,
+    pub sat: Option<f32>,
+    pub val: Option<f32>,
+    pub white: Option<f32>,
+    /// Effect parameters grouped by effect like in `/params`.
+    pub params: Value,
+}
+
+impl Defaults {
+    pub fn apply(&self, state: &mut StateStruct) {
+        if let Some(hue) = self.hue {
+            state.hue = hue;
+        }
+        if let Some(sat) = self.sat {
+            state.sat = sat;
+        }
+        if let Some(val) = self.val {
+            state.val = val;
+        }
+        if let Some(white) = self.white {
+            state.white = white;
+        }
+
+        // already validated when loading the config
+        if let Err(err) = state.params.merge(&self.params) {
+            warn!("unable to apply default parameters: {err}");
+        }
+    }
+}
+
+/// How a second strip is controlled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Combine {
+    /// Append the second strip to the first one, effects treat them as one long strip.
+    #[default]
+    Concatenate,
+    /// Give the second strip its own state and API under `/second`.
+    Independent,
+}
+
+/// Size and wiring of LEDs arranged in a grid.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MatrixFile {
+    width: usize,
+    height: usize,
+    #[serde(default)]
+    wiring: Wiring,
+}
+
+/// Settings of a strip on the second PWM channel.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SecondFile {
+    pin: Option<i32>,
+    count: Option<i32>,
+    strip_type: Option<StripType>,
+    color_order: Option<ColorOrder>,
+    brightness: Option<u8>,
+    /// Uses the gamma of the first strip if unset.
+    gamma: Option<Gamma>,
+    combine: Combine,
+    /// Layout of an independent second strip.
+    layout: Vec<Segment>,
+    matrix: Option<MatrixFile>,
+}
+
+/// Contents of the config file, every value can be left out.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct File {
+    port: Option<u16>,
+    address: Option<IpAddr>,
+    pin: Option<i32>,
+    count: Option<i32>,
+    log_level: Option<String>,
+    scripts: Option<PathBuf>,
+    script_budget: Option<u64>,
+    data: Option<PathBuf>,
+    startup: Option<String>,
+    dma: Option<i32>,
+    frequency: Option<u32>,
+    strip_type: Option<StripType>,
+    color_order: Option<ColorOrder>,
+    white_extraction: Option<WhiteExtraction>,
+    /// Color temperature of the white LEDs in Kelvin.
+    white_temperature: Option<f32>,
+    brightness: Option<u8>,
+    gamma: Option<Gamma>,
+    /// Maps the `v` value to the brightness effects render with.
+    val_curve: Option<Curve>,
+    /// Milliseconds between two updates of the controller.
+    loop_interval: Option<u64>,
+    dithering: Option<bool>,
+    /// Budget of the power supply, the brightness isn't limited if unset.
+    power: Option<Power>,
+    defaults: Defaults,
+    /// Segments the logical strip is made of, covers both strips when they are concatenated.
+    layout: Vec<Segment>,
+    /// Arranges the logical strip in a grid instead of a layout.
+    matrix: Option<MatrixFile>,
+    second: Option<SecondFile>,
+}
+
+/// A physical LED strip, the index in `Config::strips` is its PWM channel.
+#[derive(Debug, Clone, Copy)]
+#[allow(clippy::struct_field_names)]
+pub struct Strip {
+    pub pin: i32,
+    pub count: i32,
+    pub strip_type: StripType,
+    pub color_order: ColorOrder,
+    pub brightness: u8,
+    pub gamma: Gamma,
+}
+
+pub struct Config {
+    pub port: u16,
+    pub address: IpAddr,
+    pub log_level: Option<log::Level>,
+    pub scripts: Option<PathBuf>,
+    pub script_budget: Duration,
+    pub data: PathBuf,
+    pub startup: Startup,
+    pub dma: i32,
+    pub frequency: u32,
+    pub strips: Vec<Strip>,
+    pub combine: Combine,
+    /// Layout of each logical strip.
+    pub layouts: Vec<Layout>,
+    pub white_extraction: WhiteExtraction,
+    pub white_temperature: f32,
+    pub val_curve: Curve,
+    pub loop_interval: Duration,
+    pub dithering: bool,
+    pub power: Option<Power>,
+    pub defaults: Defaults,
+}
+
+impl Config {
+    /// Returns the LED counts of the strips effects render to.
+    pub fn counts(&self) -> Vec<usize> {
+        self.layouts.iter().map(Layout::count).collect()
+    }
+}
+
+fn invalid(message: impl fmt::Display + fmt::Debug + Send + Sync + 'static) -> Report<ConfigError> {
+    Report::new(ConfigError).attach_printable(message)
+}
+
+fn read(path: &Path) -> Result<File, ConfigError> {
+    let text = fs::read_to_string(path)
+        .into_report()
+        .attach_printable_lazy(|| format!("unable to read {}", path.display()))
+        .change_context(ConfigError)?;
+
+    toml::from_str(&text)
+        .into_report()
+        .attach_printable_lazy(|| format!("unable to parse {}", path.display()))
+        .change_context(ConfigError)
+}
+
+fn strip(
+    pin: Option<i32>,
+    count: Option<i32>,
+    strip_type: Option<StripType>,
+    color_order: Option<ColorOrder>,
+    brightness: Option<u8>,
+    gamma: Option<Gamma>,
+) -> Result<Strip, ConfigError> {
+    let pin =
+        pin.ok_or_else(|| invalid("the pin needs to be set with --pin or in the config file"))?;
+    if !PINS.contains(&pin) {
+        return Err(invalid(format!(
+            "pin {pin} can't drive LEDs, use one of {PINS:?}"
+        )));
+    }
+
+    let count = count
+        .ok_or_else(|| invalid("the count needs to be set with --count or in the config file"))?;
+    if count < 0 {
+        return Err(invalid(format!("count {count} can't be negative")));
+    }
+
+    let strip_type = strip_type.unwrap_or_default();
+    let color_order = color_order.unwrap_or_else(|| strip_type.color_order());
+    if color_order.white() != strip_type.color_order().white() {
+        return Err(invalid(format!(
+            "color order {color_order} doesn't match the channels of {strip_type:?} strips, e.g. {}",
+            strip_type.color_order()
+        )));
+    }
+
+    let gamma = gamma.unwrap_or_default();
+    let channels = gamma.channels();
+    if channels.iter().any(|gamma| !(0.1..=5.0).contains(gamma)) {
+        return Err(invalid(format!(
+            "gamma {channels:?} (r, g, b, w) is not in the range [0.1-5.0]"
+        )));
+    }
+
+    Ok(Strip {
+        pin,
+        count,
+        strip_type,
+        color_order,
+        brightness: brightness.unwrap_or(255),
+        gamma,
+    })
+}
+
+fn layout(
+    (segments, matrix): (Vec<Segment>, Option<MatrixFile>),
+    leds: usize,
+) -> Result<Layout, ConfigError> {
+    let layout = match matrix {
+        Some(_) if !segments.is_empty() => {
+            return Err(invalid("a strip can't have both a layout and a matrix"));
+        }
+        Some(matrix) => Layout::grid(
+            Matrix {
+                width: matrix.width,
+                height: matrix.height,
+            },
+            matrix.wiring,
+            leds,
+        ),
+        None => Layout::new(&segments, leds),
+    };
+
+    layout.into_report().change_context(ConfigError)
+}
+
+/// Loads the config file (if any) and overrides its values with the command line options.
+#[allow(clippy::too_many_lines)]
+pub fn load(cli: Cli) -> Result<Config, ConfigError> {
+    let file = match &cli.config {
+        Some(path) => read(path)?,
+        None => File::default(),
+    };
+
+    let mut strips = vec![strip(
+        cli.pin.or(file.pin),
+        cli.count.or(file.count),
+        file.strip_type,
+        file.color_order,
+        file.brightness,
+        file.gamma,
+    )?];
+
+    let mut arrangements = vec![(file.layout, file.matrix)];
+
+    let combine = if let Some(second) = file.second {
+        let second_strip = strip(
+            second.pin,
+            second.count,
+            second.strip_type,
+            second.color_order,
+            second.brightness,
+            second.gamma.or(file.gamma),
+        )
+        .attach_printable("invalid second strip")?;
+
+        // both strips need a PWM channel of their own
+        if !PWM0_PINS.contains(&strips[0].pin) || !PWM1_PINS.contains(&second_strip.pin) {
+            return Err(invalid(format!(
+                "two strips need the first pin on PWM channel 0 {PWM0_PINS:?} and the second on PWM channel 1 {PWM1_PINS:?}"
+            )));
+        }
+
+        strips.push(second_strip);
+
+        match second.combine {
+            Combine::Concatenate if !second.layout.is_empty() || second.matrix.is_some() => {
+                return Err(invalid(
+                    "a concatenated second strip is part of the layout of the first one",
+                ));
+            }
+            Combine::Concatenate => {}
+            Combine::Independent => arrangements.push((second.layout, second.matrix)),
+        }
+
+        second.combine
+    } else {
+        Combine::default()
+    };
+
+    // concatenated strips form one logical strip
+    let leds: Vec<usize> = match combine {
+        Combine::Concatenate => vec![strips.iter().map(|strip| strip.count as usize).sum()],
+        Combine::Independent => strips.iter().map(|strip| strip.count as usize).collect(),
+    };
+
+    let layouts = arrangements
+        .into_iter()
+        .zip(leds)
+        .map(|(arrangement, leds)| layout(arrangement, leds))
+        .collect::<Result<_, _>>()?;
+
+    let log_level = match cli.log_level {
+        Some(level) => Some(level),
+        None => file
+            .log_level
+            .map(|level| {
+                level
+                    .parse()
+                    .into_report()
+                    .attach_printable_lazy(|| format!("unknown log level {level}"))
+                    .change_context(ConfigError)
+            })
+            .transpose()?,
+    };
+
+    let data = cli
+        .data
+        .or(file.data)
+        .unwrap_or_else(|| PathBuf::from("data"));
+
+    let startup = cli
+        .startup
+        .or_else(|| file.startup.and_then(|startup| startup.parse().ok()))
+        .unwrap_or(Startup::Restore);
+
+    // catch typos in the preset name now instead of silently starting with the LEDs off
+    if let Startup::Preset(preset) = &startup {
+        let exists = Storage::new(data.clone())
+            .exists(PRESETS, preset)
+            .attach_printable_lazy(|| format!("invalid startup preset {preset}"))
+            .change_context(ConfigError)?;
+
+        if !exists {
+            return Err(invalid(format!(
+                "startup preset {preset} doesn't exist in {}",
+                data.display()
+            )));
+        }
+    }
+
+    let dma = file.dma.unwrap_or(10);
+    if !(0..=14).contains(&dma) {
+        return Err(invalid(format!(
+            "dma channel {dma} is not in the range [0-14]"
+        )));
+    }
+
+    let frequency = file.frequency.unwrap_or(800_000);
+    if ![400_000, 800_000].contains(&frequency) {
+        return Err(invalid(format!(
+            "frequency {frequency} is neither 400000 nor 800000"
+        )));
+    }
+
+    let loop_interval = file.loop_interval.unwrap_or(10);
+    if !(1..=1000).contains(&loop_interval) {
+        return Err(invalid(format!(
+            "loop interval {loop_interval} is not in the range [1-1000] ms"
+        )));
+    }
+
+    let white_temperature = file.white_temperature.unwrap_or(4500.0);
+    if !(1000.0..=12000.0).contains(&white_temperature) {
+        return Err(invalid(format!(
+            "white temperature {white_temperature} is not in the range [1000-12000] K"
+        )));
+    }
+
+    if let Some(power) = &file.power {
+        let leds = strips.iter().map(|strip| strip.count as usize).sum();
+
+        if power.led_current <= 0.0 || power.idle_current < 0.0 {
+            return Err(invalid("the current of the LEDs needs to be positive"));
+        }
+
+        if power.max_current <= power.idle(leds) {
+            return Err(invalid(format!(
+                "max current {} mA is not above the {} mA {leds} LEDs draw when off",
+                power.max_current,
+                power.idle(leds)
+            )));
+        }
+    }
+
+    Params::default()
+        .merge(&file.defaults.params)
+        .into_report()
+        .attach_printable("invalid default parameters")
+        .change_context(ConfigError)?;
+
+    Ok(Config {
+        port: cli.port.or(file.port).unwrap_or(88),
+        address: cli
+            .address
+            .or(file.address)
+            .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+        log_level,
+        scripts: cli.scripts.or(file.scripts),
+        script_budget: Duration::from_millis(
+            cli.script_budget.or(file.script_budget).unwrap_or(20),
+        ),
+        data,
+        startup,
+        dma,
+        frequency,
+        strips,
+        combine,
+        layouts,
+        white_extraction: file.white_extraction.unwrap_or_default(),
+        white_temperature,
+        val_curve: file.val_curve.unwrap_or_default(),
+        loop_interval: Duration::from_millis(loop_interval),
+        dithering: file.dithering.unwrap_or(false),
+        power: file.power,
+        defaults: file.defaults,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+    use crate::storage::TempDir;
+
+    /// Loads a config file with the given contents and command line options.
+    fn load_with(test: &str, file: &str, args: &[&str]) -> Result<Config, ConfigError> {
+        let dir = TempDir::new(&format!("config-{test}"))
+            .into_report()
+            .change_context(ConfigError)?;
+
+        let path = dir.path().join("config.toml");
+        fs::write(&path, file)
+            .into_report()
+            .change_context(ConfigError)?;
+
+        let mut cli = vec!["lighting-manager", "--config"];
+        cli.push(path.to_str().unwrap_or_default());
+        cli.extend(args);
+
+        load(Cli::parse_from(cli))
+    }
+
+    #[test]
+    fn command_line_overrides_the_file() -> Result<(), ConfigError> {
+        let config = load_with(
+            "overrides",
+            "pin = 12\ncount = 10\nport = 8080\nstartup = \"off\"",
+            &["--port", "9090"],
+        )?;
+
+        assert_eq!(config.port, 9090);
+        assert_eq!(config.strips[0].pin, 12);
+        assert_eq!(config.counts(), [10]);
+        assert!(matches!(config.startup, Startup::Off));
+        assert_eq!(config.strips[0].color_order, ColorOrder::GRB);
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        let invalid = |test, file| load_with(test, file, &[]).is_err();
+
+        assert!(invalid("pin", "pin = 4\ncount = 10"));
+        assert!(invalid("count", "pin = 18"));
+        assert!(invalid("dma", "pin = 18\ncount = 10\ndma = 15"));
+        assert!(invalid("loop", "pin = 18\ncount = 10\nloop_interval = 0"));
+        assert!(invalid("unknown", "pin = 18\ncount = 10\ncolour = 1"));
+        assert!(invalid(
+            "order",
+            "pin = 18\ncount = 10\ncolor_order = \"grbw\""
+        ));
+        assert!(invalid(
+            "params",
+            "pin = 18\ncount = 10\n[defaults.params.fire]\nheat = 1"
+        ));
+    }
+
+    #[test]
+    fn startup_preset_needs_to_exist() -> Result<(), ConfigError> {
+        let dir = TempDir::new("config-presets")
+            .into_report()
+            .change_context(ConfigError)?;
+        let data = dir.path().to_str().unwrap_or_default();
+        let args = ["--data", data, "--startup", "evening"];
+
+        assert!(load_with("missing", "pin = 18\ncount = 10", &args).is_err());
+
+        // only the existence of the preset is checked when loading the config
+        Storage::new(data.into())
+            .save(PRESETS, "evening", &Value::Null)
+            .change_context(ConfigError)?;
+
+        let config = load_with("existing", "pin = 18\ncount = 10", &args)?;
+        assert!(matches!(config.startup, Startup::Preset(name) if name == "evening"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn second_strip_needs_the_other_pwm_channel() -> Result<(), ConfigError> {
+        let config = load_with(
+            "concatenated",
+            "pin = 18\ncount = 10\n[second]\npin = 13\ncount = 5",
+            &[],
+        )?;
+        assert_eq!(config.strips.len(), 2);
+        assert_eq!(config.counts(), [15]);
+
+        let config = load_with(
+            "independent",
+            "pin = 18\ncount = 10\n[second]\npin = 13\ncount = 5\ncombine = \"independent\"",
+            &[],
+        )?;
+        assert_eq!(config.counts(), [10, 5]);
+
+        assert!(load_with(
+            "same-channel",
+            "pin = 18\ncount = 10\n[second]\npin = 12\ncount = 5",
+            &[],
+        )
+        .is_err());
+        assert!(load_with(
+            "second-layout",
+            "pin = 18\ncount = 10\n[second]\npin = 13\ncount = 5\nlayout = [{ count = 5 }]",
+            &[],
+        )
+        .is_err());
+
+        Ok(())
+    }
+}
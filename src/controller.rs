@@ -1,5 +1,6 @@
 use std::error::Error;
 use std::fmt;
+use std::time::{Duration, Instant};
 
 use error_stack::Result;
 #[cfg(target_arch = "arm")]
@@ -9,15 +10,54 @@ use tokio::sync::MutexGuard;
 #[cfg(target_arch = "arm")]
 use rs_ws281x::{ChannelBuilder, Controller, ControllerBuilder, StripType};
 
-use crate::pixel::Pixel;
-#[cfg(target_arch = "arm")]
-use crate::state::Mode;
-use crate::state::StateStruct;
+use crate::config::{Combine, Config};
+use crate::effects::{self, lerp, script::Runner, Context, Curve};
+use crate::layout::Layout;
+use crate::pixel::{Output, Pixel, RawColor, White};
+use crate::power::{Power, Status};
+use crate::state::{Mode, StateStruct};
 
 pub struct Data {
     #[cfg(target_arch = "arm")]
     controller: Controller,
-    progress_old: f32,
+    engines: Vec<Engine>,
+    channels: Vec<Channel>,
+    scripts: Runner,
+    /// Output every frame so the dithering can average the values over time.
+    dithering: bool,
+    power: Option<Power>,
+    /// Power limit applied to the last frame.
+    status: Option<Status>,
+}
+
+/// Renders the effects of one state into a frame.
+struct Engine {
+    frame: Vec<Pixel>,
+    layout: Layout,
+    val_curve: Curve,
+    last_update: Instant,
+    started: Instant,
+    memory: effects::Memory,
+    fade: Option<Fade>,
+}
+
+/// A physical strip showing a part of the LEDs of an engine.
+struct Channel {
+    engine: usize,
+    start: usize,
+    count: usize,
+    output: Output,
+    /// Global brightness of the strip applied by the ws281x library [0-1].
+    brightness: f32,
+    /// Rounding errors carried to the next frame per LED, only kept with dithering.
+    residuals: Option<Vec<[f32; 4]>>,
+}
+
+/// Blend from a snapshot of the previous mode into the current one.
+struct Fade {
+    from: Vec<Pixel>,
+    start: Instant,
+    duration: Duration,
 }
 
 #[derive(Debug)]
@@ -31,33 +71,104 @@ impl fmt::Display for ControllerError {
 
 impl Error for ControllerError {}
 
+/// Creates one engine per logical strip and assigns the physical strips to them.
+fn layout(config: &Config) -> (Vec<Engine>, Vec<Channel>) {
+    let engines: Vec<_> = config
+        .layouts
+        .iter()
+        .map(|layout| Engine {
+            frame: vec![Pixel::OFF; layout.count()],
+            layout: layout.clone(),
+            val_curve: config.val_curve,
+            last_update: Instant::now(),
+            started: Instant::now(),
+            memory: effects::Memory::default(),
+            fade: None,
+        })
+        .collect();
+
+    // concatenated strips continue where the previous one ended
+    let mut offsets = vec![0; engines.len()];
+    let channels = config
+        .strips
+        .iter()
+        .enumerate()
+        .map(|(i, strip)| {
+            let engine = match config.combine {
+                Combine::Concatenate => 0,
+                Combine::Independent => i,
+            };
+
+            let start = offsets[engine];
+            offsets[engine] += strip.count as usize;
+
+            Channel {
+                engine,
+                start,
+                count: strip.count as usize,
+                output: Output {
+                    order: strip.color_order,
+                    white: White::new(config.white_extraction, config.white_temperature),
+                    gamma: strip.gamma,
+                    scale: 1.0,
+                },
+                brightness: f32::from(strip.brightness) / 255.0,
+                residuals: config
+                    .dithering
+                    .then(|| vec![[0.0; 4]; strip.count as usize]),
+            }
+        })
+        .collect();
+
+    (engines, channels)
+}
+
 #[cfg(target_arch = "arm")]
-pub fn init(pin: i32, count: i32) -> Result<Data, ControllerError> {
-    let controller = ControllerBuilder::new()
-        .freq(800_000)
-        .dma(10)
-        .channel(
-            0,
+pub fn init(config: &Config, scripts: Runner) -> Result<Data, ControllerError> {
+    let mut builder = ControllerBuilder::new();
+    builder.freq(config.frequency).dma(config.dma);
+
+    for (i, strip) in config.strips.iter().enumerate() {
+        builder.channel(
+            i,
             ChannelBuilder::new()
-                .pin(pin)
-                .count(count)
-                .strip_type(StripType::Ws2811Grb)
-                .brightness(255)
+                .pin(strip.pin)
+                .count(strip.count)
+                // the channels are ordered when packing the colors
+                .strip_type(if strip.color_order.white() {
+                    StripType::Sk6812Rgbw
+                } else {
+                    StripType::Ws2811Rgb
+                })
+                .brightness(strip.brightness)
                 .build(),
-        )
+        );
+    }
+
+    let controller = builder
         .build()
         .into_report()
         .attach_printable_lazy(|| {
-            format!(
-                "could not create controller on pin {} with {} leds",
-                pin, count
-            )
+            let strips: Vec<_> = config
+                .strips
+                .iter()
+                .map(|strip| format!("pin {} with {} leds", strip.pin, strip.count))
+                .collect();
+
+            format!("could not create controller on {}", strips.join(" and "))
         })
         .change_context(ControllerError)?;
 
+    let (engines, channels) = layout(config);
+
     let mut data = Data {
         controller,
-        progress_old: 0.0,
+        engines,
+        channels,
+        scripts,
+        dithering: config.dithering,
+        power: config.power,
+        status: None,
     };
 
     data.off()?;
@@ -66,8 +177,31 @@ pub fn init(pin: i32, count: i32) -> Result<Data, ControllerError> {
 }
 
 #[cfg(not(target_arch = "arm"))]
-pub fn init(_pin: i32, _count: i32) -> Result<Data, ControllerError> {
-    let mut data = Data { progress_old: 0.0 };
+pub fn init(config: &Config, scripts: Runner) -> Result<Data, ControllerError> {
+    // there is no hardware to drive, only show what would have been used
+    for strip in &config.strips {
+        info!(
+            "no LED hardware on this platform: pin {}, {} {:?} LEDs ({}), dma {}, {} Hz, brightness {}",
+            strip.pin,
+            strip.count,
+            strip.strip_type,
+            strip.color_order,
+            config.dma,
+            config.frequency,
+            strip.brightness
+        );
+    }
+
+    let (engines, channels) = layout(config);
+
+    let mut data = Data {
+        engines,
+        channels,
+        scripts,
+        dithering: config.dithering,
+        power: config.power,
+        status: None,
+    };
 
     data.off()?;
 
@@ -75,177 +209,441 @@ pub fn init(_pin: i32, _count: i32) -> Result<Data, ControllerError> {
 }
 
 impl Data {
+    /// Renders every engine with its state and outputs the frames if any of them changed.
+    pub fn update(
+        &mut self,
+        states: &mut [MutexGuard<StateStruct>],
+    ) -> Result<(), ControllerError> {
+        let mut render = false;
+
+        // all strips share the scripts, so a reload requested by any of them applies to all
+        if states.iter().any(|state| state.reload_scripts) {
+            let scripts = self.scripts.load();
+
+            for (engine, state) in self.engines.iter_mut().zip(states.iter_mut()) {
+                state.reload_scripts = false;
+                state.scripts = scripts.clone();
+                // restart the script with its new version
+                engine.memory.script = None;
+            }
+        }
+
+        for (engine, state) in self.engines.iter_mut().zip(states.iter_mut()) {
+            render |= engine.update(state, &self.scripts);
+        }
+
+        if render || self.dithering {
+            self.output()?;
+
+            for state in states.iter_mut() {
+                state.power = self.status;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn off(&mut self) -> Result<(), ControllerError> {
+        for engine in &mut self.engines {
+            for led in &mut engine.frame {
+                *led = Pixel::OFF;
+            }
+        }
+
+        self.output()
+    }
+
+    /// Scales the brightness of all channels down if the frame would draw too much current.
+    fn limit(&mut self) {
+        let Some(power) = self.power else {
+            return;
+        };
+
+        let leds = self.channels.iter().map(|channel| channel.count).sum();
+        let load = self
+            .channels
+            .iter()
+            .map(|channel| channel.load(&self.engines[channel.engine]))
+            .sum();
+
+        let status = power.limit(leds, load);
+
+        for channel in &mut self.channels {
+            channel.output.scale = status.scale;
+        }
+
+        self.status = Some(status);
+    }
+
     #[cfg(target_arch = "arm")]
-    pub fn update(&mut self, mut state: MutexGuard<StateStruct>) -> Result<(), ControllerError> {
+    fn output(&mut self) -> Result<(), ControllerError> {
+        self.limit();
+
+        for (i, channel) in self.channels.iter_mut().enumerate() {
+            channel.quantize(&self.engines[channel.engine], self.controller.leds_mut(i));
+        }
+
+        self.controller
+            .render()
+            .into_report()
+            .attach_printable_lazy(|| "unable to render new values")
+            .change_context(ControllerError)?;
+
+        Ok(())
+    }
+
+    #[cfg(not(target_arch = "arm"))]
+    #[allow(clippy::unnecessary_wraps)]
+    fn output(&mut self) -> Result<(), ControllerError> {
+        self.limit();
+
+        for channel in &mut self.channels {
+            let mut leds = vec![[0; 4]; channel.count];
+            channel.quantize(&self.engines[channel.engine], &mut leds);
+        }
+
+        Ok(())
+    }
+}
+
+impl Channel {
+    /// Returns the sum of all channels of the LEDs as driven, in the range [0-1] per channel.
+    fn load(&self, engine: &Engine) -> f32 {
+        engine
+            .pixels(self)
+            .map(|pixel| pixel.to_output(&self.output).iter().sum::<f32>())
+            .sum::<f32>()
+            * self.brightness
+    }
+
+    /// Turns the pixels of the engine shown by this channel into raw colors.
+    fn quantize(&mut self, engine: &Engine, leds: &mut [RawColor]) {
+        let pixels = engine.pixels(self);
+
+        match &mut self.residuals {
+            Some(residuals) => {
+                for ((led, pixel), residual) in leds.iter_mut().zip(pixels).zip(residuals) {
+                    *led = pixel.to_u8_dithered(&self.output, residual);
+                }
+            }
+            None => {
+                for (led, pixel) in leds.iter_mut().zip(pixels) {
+                    *led = pixel.to_u8(&self.output);
+                }
+            }
+        }
+    }
+}
+
+impl Engine {
+    /// Returns the pixels shown by the LEDs of a channel.
+    fn pixels(&self, channel: &Channel) -> impl Iterator<Item = Pixel> + '_ {
+        (channel.start..channel.start + channel.count)
+            .map(|led| self.layout.pixel(&self.frame, led))
+    }
+
+    /// Renders the current mode, returns whether the frame needs to be output.
+    #[allow(clippy::too_many_lines)]
+    fn update(&mut self, state: &mut StateStruct, scripts: &Runner) -> bool {
+        state.update_playlist();
+
+        // effects render with the brightness the `v` value stands for
+        let val = self.val_curve.apply(state.val);
+
         let delta_time = state.start.elapsed();
         let progress = ((delta_time.as_millis() % state.interval.as_millis()) as f32)
             / (state.interval.as_millis() as f32);
 
-        let leds = self.controller.leds_mut(0);
+        let ctx = Context {
+            elapsed: delta_time,
+            delta: self.last_update.elapsed(),
+            matrix: self.layout.matrix(),
+        };
+        self.last_update = Instant::now();
+
+        // forget the state of previous effects when a mode gets (re)started
+        if self.started != state.start {
+            self.started = state.start;
+            self.memory = effects::Memory::default();
+        }
+
+        if let Some(duration) = state.fade.take() {
+            self.fade = Some(Fade {
+                from: self.frame.clone(),
+                start: Instant::now(),
+                duration,
+            });
+        }
+
+        let leds = self.frame.as_mut_slice();
 
         match state.mode {
             Mode::OFF => {
-                let pixel_u8 = Pixel::OFF.to_u8();
                 for led in leds {
-                    *led = pixel_u8;
+                    *led = Pixel::OFF;
                 }
             }
             Mode::STATIC => {
-                let pixel_u8 = Pixel::HSV {
+                let mut pixel = Pixel::HSV {
                     h: state.hue,
                     s: state.sat,
-                    v: state.val,
+                    v: val,
+                };
+
+                if state.white > 0.0 {
+                    let (r, g, b) = pixel.to_rgb();
+                    pixel = Pixel::RGBW {
+                        r,
+                        g,
+                        b,
+                        w: state.white,
+                    };
                 }
-                .to_u8();
+
                 for led in leds {
-                    *led = pixel_u8;
+                    *led = pixel;
                 }
             }
             Mode::RAINBOW => {
                 for (i, led) in leds.iter_mut().enumerate() {
                     let rainbow_hue = 6000.0f32.mul_add(progress, i as f32) * (360.0 / 150.0);
 
-                    let pixel = Pixel::HSV {
+                    *led = Pixel::HSV {
                         h: rainbow_hue,
                         s: state.sat,
-                        v: state.val,
+                        v: val,
                     };
-
-                    *led = pixel.to_u8();
                 }
 
                 state.render = true;
             }
             Mode::SLEEP => {
-                if progress <= self.progress_old && self.progress_old > 0.0 {
-                    state.mode = Mode::OFF;
-                    self.progress_old = 0.0;
-                } else {
-                    self.progress_old = progress;
-                }
+                let params = &state.params.sleep;
 
-                let sleep_sat = state.sat - progress * (state.sat / 2.0);
-                let sleep_val = state.val - state.val * progress;
+                effects::sleep::render(&ctx, params, state.hue, state.sat, val, leds);
 
-                for (i, led) in leds.iter_mut().enumerate() {
-                    let sleep_hue = 6000.0f32.mul_add(progress, i as f32) * (360.0 / 150.0);
-
-                    let pixel = Pixel::HSV {
-                        h: sleep_hue,
-                        s: sleep_sat,
-                        v: sleep_val,
-                    };
-
-                    *led = pixel.to_u8();
+                if effects::sleep::progress(&ctx, params) >= 1.0 {
+                    let end_mode = params.end_mode;
+                    state.set_mode(end_mode);
                 }
 
                 state.render = true;
             }
             Mode::ALARM => {
-                let pixel_u8 = if progress >= 0.5 {
-                    Pixel::RED.to_u8()
+                let pixel = if progress >= 0.5 {
+                    Pixel::RED
                 } else {
-                    Pixel::OFF.to_u8()
+                    Pixel::OFF
                 };
 
                 for led in leds {
-                    *led = pixel_u8;
+                    *led = pixel;
                 }
 
                 state.render = true;
             }
             Mode::COLORRAPE => {
-                let pixel_u8 = Pixel::HSV {
+                let pixel = Pixel::HSV {
                     h: progress * 360.0,
                     s: state.sat,
-                    v: state.val,
-                }
-                .to_u8();
+                    v: val,
+                };
 
                 for led in leds {
-                    *led = pixel_u8;
+                    *led = pixel;
                 }
 
                 state.render = true;
             }
             Mode::STROBE => {
-                let pixel_u8 = if progress >= 0.5 {
-                    Pixel::WHITE.to_u8()
+                let pixel = if progress >= 0.5 {
+                    Pixel::WHITE
                 } else {
-                    Pixel::OFF.to_u8()
+                    Pixel::OFF
                 };
 
                 for led in leds {
-                    *led = pixel_u8;
+                    *led = pixel;
                 }
 
                 state.render = true;
             }
             Mode::IDENTIFY => {
-                let id_pixel = Pixel::RED.to_u8();
-                let other_pixel = Pixel::WHITE.to_u8();
-
                 for (i, led) in leds.iter_mut().enumerate() {
                     *led = if i == state.hue as usize {
-                        id_pixel
+                        Pixel::RED
                     } else {
-                        other_pixel
+                        Pixel::WHITE
                     };
                 }
             }
-        }
+            Mode::SUNRISE => {
+                effects::sunrise::render(&ctx, &state.params.sunrise, val, leds);
 
-        if state.render {
-            state.render = false;
+                // keep rendering until the final color is reached
+                if effects::sunrise::progress(&ctx, &state.params.sunrise) < 1.0 {
+                    state.render = true;
+                }
+            }
+            Mode::FIRE => {
+                effects::fire::render(&ctx, &state.params.fire, val, &mut self.memory.fire, leds);
 
-            self.controller
-                .render()
-                .into_report()
-                .attach_printable_lazy(|| "unable to render new values")
-                .change_context(ControllerError)?;
-        }
+                state.render = true;
+            }
+            Mode::TWINKLE => {
+                effects::twinkle::render(
+                    &ctx,
+                    &state.params.twinkle,
+                    &mut self.memory.twinkle,
+                    state.hue,
+                    state.sat,
+                    val,
+                    leds,
+                );
 
-        Ok(())
-    }
+                state.render = true;
+            }
+            Mode::SPARKLE => {
+                effects::sparkle::render(
+                    &ctx,
+                    &state.params.sparkle,
+                    &mut self.memory.twinkle,
+                    state.hue,
+                    state.sat,
+                    val,
+                    leds,
+                );
 
-    #[cfg(target_arch = "arm")]
-    pub fn off(&mut self) -> Result<(), ControllerError> {
-        let leds = self.controller.leds_mut(0);
+                state.render = true;
+            }
+            Mode::CHASE => {
+                effects::chase::render(&ctx, &state.params.chase, state.hue, state.sat, val, leds);
 
-        for led in leds {
-            *led = Pixel::OFF.to_u8();
-        }
+                state.render = true;
+            }
+            Mode::COMET => {
+                effects::comet::render(&ctx, &state.params.comet, state.hue, state.sat, val, leds);
 
-        self.controller
-            .render()
-            .into_report()
-            .attach_printable_lazy(|| "unable to turn off all LEDs")
-            .change_context(ControllerError)?;
+                state.render = true;
+            }
+            Mode::SCANNER => {
+                effects::scanner::render(
+                    &ctx,
+                    &state.params.scanner,
+                    state.hue,
+                    state.sat,
+                    val,
+                    leds,
+                );
 
-        Ok(())
-    }
+                state.render = true;
+            }
+            Mode::BREATHE => {
+                effects::breathe::render(
+                    &ctx,
+                    &state.params.breathe,
+                    state.hue,
+                    state.sat,
+                    val,
+                    leds,
+                );
 
-    #[cfg(not(target_arch = "arm"))]
-    pub fn update(&mut self, mut _state: MutexGuard<StateStruct>) -> Result<(), ControllerError> {
-        if self.progress_old < 0.0 {
-            Err(ControllerError)?;
-        }
+                state.render = true;
+            }
+            Mode::GRADIENT => {
+                effects::gradient::render(&ctx, &state.params.gradient, val, leds);
 
-        Ok(())
-    }
+                if state.params.gradient.speed != 0.0 {
+                    state.render = true;
+                }
+            }
+            Mode::PLASMA => {
+                effects::plasma::render(&ctx, &state.params.plasma, val, leds);
 
-    #[cfg(not(target_arch = "arm"))]
-    pub fn off(&mut self) -> Result<(), ControllerError> {
-        if self.progress_old < 0.0 {
-            Err(ControllerError)?;
+                state.render = true;
+            }
+            Mode::LAVA => {
+                effects::lava::render(&ctx, &state.params.lava, val, leds);
+
+                state.render = true;
+            }
+            Mode::AURORA => {
+                effects::aurora::render(&ctx, &state.params.aurora, val, leds);
+
+                state.render = true;
+            }
+            Mode::CUSTOM => {
+                effects::custom::render(
+                    &ctx,
+                    state.custom.as_ref(),
+                    progress,
+                    state.hue,
+                    state.sat,
+                    val,
+                    leds,
+                );
+
+                state.render = true;
+            }
+            Mode::SCRIPT => {
+                scripts.render(&ctx, &state.params.script, &mut self.memory.script, leds);
+
+                state.render = true;
+            }
+            Mode::DIRECT => {
+                effects::direct::render(&state.direct, leds);
+
+                if state
+                    .direct_deadline
+                    .is_some_and(|deadline| deadline <= Instant::now())
+                {
+                    state.direct_deadline = None;
+                    let previous = state.previous_mode;
+                    state.set_mode(previous);
+                }
+            }
+            Mode::SEQUENCE => {
+                let sequence = state.sequence.as_ref().map(|(_, sequence)| sequence);
+
+                effects::sequence::render(&ctx, sequence, leds);
+
+                // a sequence played once keeps showing its last frame
+                if !sequence.is_some_and(|sequence| sequence.finished(&ctx)) {
+                    state.render = true;
+                }
+            }
+            Mode::TEXT => {
+                effects::text::render(&ctx, &state.params.text, state.hue, state.sat, val, leds);
+
+                state.render = true;
+            }
         }
 
-        let leds = &mut [[0, 0, 0, 0]];
+        if let Some(fade) = &self.fade {
+            let t = fade.start.elapsed().as_secs_f32() / fade.duration.as_secs_f32();
+
+            if t < 1.0 {
+                for (led, from) in self.frame.iter_mut().zip(&fade.from) {
+                    let (r0, g0, b0, w0) = from.to_rgbw();
+                    let (r1, g1, b1, w1) = led.to_rgbw();
+
+                    *led = Pixel::RGBW {
+                        r: lerp(r0, r1, t),
+                        g: lerp(g0, g1, t),
+                        b: lerp(b0, b1, t),
+                        w: lerp(w0, w1, t),
+                    };
+                }
+            } else {
+                self.fade = None;
+            }
 
-        for led in leds {
-            *led = Pixel::OFF.to_u8();
+            state.render = true;
         }
 
-        Ok(())
+        let render = state.render;
+        state.render = false;
+
+        render
     }
 }
@@ -0,0 +1,399 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::layout::Matrix;
+use crate::pixel::{Color, Pixel};
+use crate::state::Mode;
+
+pub mod aurora;
+pub mod breathe;
+pub mod chase;
+pub mod comet;
+pub mod custom;
+pub mod direct;
+pub mod fire;
+pub mod gradient;
+pub mod lava;
+pub mod noise;
+pub mod palette;
+pub mod plasma;
+pub mod scanner;
+pub mod script;
+pub mod sequence;
+pub mod sleep;
+pub mod sparkle;
+pub mod sunrise;
+pub mod text;
+pub mod twinkle;
+
+/// Timing information passed to every effect when rendering a frame.
+pub struct Context {
+    /// Time since the current mode was started.
+    pub elapsed: Duration,
+    /// Time since the previous frame.
+    pub delta: Duration,
+    /// Grid the pixels are arranged in, a strip is a single row.
+    pub matrix: Matrix,
+}
+
+/// Tunable parameters of all effects, grouped by effect.
+///
+/// Every group is exposed through the `/params/{EFFECT}` API route
+/// under the name of its field.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Params {
+    pub aurora: aurora::Params,
+    pub breathe: breathe::Params,
+    pub chase: chase::Params,
+    pub comet: comet::Params,
+    pub fire: fire::Params,
+    pub gradient: gradient::Params,
+    pub lava: lava::Params,
+    pub plasma: plasma::Params,
+    pub scanner: scanner::Params,
+    pub script: script::Params,
+    pub sleep: sleep::Params,
+    pub sparkle: sparkle::Params,
+    pub sunrise: sunrise::Params,
+    pub text: text::Params,
+    pub twinkle: twinkle::Params,
+}
+
+/// State of all effects that persists across frames.
+///
+/// The controller replaces it with a fresh one whenever a mode is (re)started.
+#[derive(Default)]
+pub struct Memory {
+    pub fire: fire::Simulation,
+    pub twinkle: PixelMemory<twinkle::Cell>,
+    pub script: Option<script::Memory>,
+}
+
+/// Per-LED state of an effect.
+#[derive(Default)]
+pub struct PixelMemory<T> {
+    cells: Vec<T>,
+}
+
+impl<T: Default + Clone> PixelMemory<T> {
+    /// Returns one cell per LED, resetting all cells if the LED count changed.
+    pub fn cells(&mut self, count: usize) -> &mut [T] {
+        if self.cells.len() != count {
+            self.cells = vec![T::default(); count];
+        }
+
+        &mut self.cells
+    }
+}
+
+/// Direction in which patterns move along the strip.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    #[default]
+    Forward,
+    Backward,
+}
+
+impl Direction {
+    /// Maps an effect index onto the strip so that patterns move in this direction.
+    pub const fn index(self, i: usize, count: usize) -> usize {
+        match self {
+            Self::Forward => i,
+            Self::Backward => count - 1 - i,
+        }
+    }
+}
+
+/// Easing curves used for fades.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Curve {
+    #[default]
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+    Perceptual,
+}
+
+impl Curve {
+    /// Maps `t` in the range [0-1] onto the curve.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+
+        match self {
+            Self::Linear => t,
+            Self::EaseIn => t * t,
+            Self::EaseOut => t * (2.0 - t),
+            Self::EaseInOut => t * t * 2.0f32.mul_add(-t, 3.0),
+            Self::Perceptual => perceptual(t),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ParamsError {
+    UnknownEffect(String),
+    UnknownParam(String),
+    Invalid(String),
+}
+
+impl fmt::Display for ParamsError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownEffect(effect) => write!(fmt, "Unknown effect: {effect}"),
+            Self::UnknownParam(param) => write!(fmt, "Unknown parameter: {param}"),
+            Self::Invalid(reason) => write!(fmt, "Invalid parameters: {reason}"),
+        }
+    }
+}
+
+impl Error for ParamsError {}
+
+impl Params {
+    /// Returns the parameters of a single effect as JSON.
+    pub fn get(&self, effect: &str) -> Result<Value, ParamsError> {
+        serde_json::to_value(self)
+            .map_err(|err| ParamsError::Invalid(err.to_string()))?
+            .get(effect)
+            .cloned()
+            .ok_or_else(|| ParamsError::UnknownEffect(effect.to_owned()))
+    }
+
+    /// Updates the parameters of a single effect from textual key-value pairs.
+    ///
+    /// Values are parsed as JSON first and fall back to plain strings,
+    /// so `duration=600` and `direction=backward` both work.
+    /// Nothing is changed if any of the values is invalid.
+    pub fn set(
+        &mut self,
+        effect: &str,
+        values: &HashMap<String, String>,
+    ) -> Result<Value, ParamsError> {
+        let mut all =
+            serde_json::to_value(&*self).map_err(|err| ParamsError::Invalid(err.to_string()))?;
+
+        let group = all
+            .get_mut(effect)
+            .and_then(Value::as_object_mut)
+            .ok_or_else(|| ParamsError::UnknownEffect(effect.to_owned()))?;
+
+        for (key, value) in values {
+            if !group.contains_key(key) {
+                return Err(ParamsError::UnknownParam(key.clone()));
+            }
+
+            let parsed =
+                serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.clone()));
+            group.insert(key.clone(), parsed);
+        }
+
+        *self = Self::from_value(all)?;
+
+        self.get(effect)
+    }
+
+    /// Updates the parameters of any number of effects from JSON grouped by effect,
+    /// e.g. `{"fire": {"cooling": 80}}`.
+    ///
+    /// Nothing is changed if any of the values is invalid.
+    pub fn merge(&mut self, changes: &Value) -> Result<(), ParamsError> {
+        let Some(changes) = changes.as_object() else {
+            return if changes.is_null() {
+                Ok(())
+            } else {
+                Err(ParamsError::Invalid("expected an object".to_owned()))
+            };
+        };
+
+        let mut all =
+            serde_json::to_value(&*self).map_err(|err| ParamsError::Invalid(err.to_string()))?;
+
+        for (effect, values) in changes {
+            let group = all
+                .get_mut(effect)
+                .and_then(Value::as_object_mut)
+                .ok_or_else(|| ParamsError::UnknownEffect(effect.clone()))?;
+
+            let values = values
+                .as_object()
+                .ok_or_else(|| ParamsError::Invalid(format!("expected an object for {effect}")))?;
+
+            for (key, value) in values {
+                if !group.contains_key(key) {
+                    return Err(ParamsError::UnknownParam(key.clone()));
+                }
+
+                group.insert(key.clone(), value.clone());
+            }
+        }
+
+        *self = Self::from_value(all)?;
+
+        Ok(())
+    }
+
+    /// Deserializes all parameters and rejects combinations effects can't handle.
+    fn from_value(all: Value) -> Result<Self, ParamsError> {
+        let params: Self =
+            serde_json::from_value(all).map_err(|err| ParamsError::Invalid(err.to_string()))?;
+
+        // SLEEP would restart itself every time the fade ends
+        if params.sleep.end_mode == Mode::SLEEP {
+            return Err(ParamsError::Invalid("SLEEP can't end in SLEEP".to_owned()));
+        }
+
+        Ok(params)
+    }
+}
+
+/// Returns the red, green and blue components of `color` dimmed to `val`,
+/// or of the current color if unset.
+pub fn color_or_current(color: Option<Color>, hue: f32, sat: f32, val: f32) -> (f32, f32, f32) {
+    color.map_or_else(
+        || {
+            Pixel::HSV {
+                h: hue,
+                s: sat,
+                v: val,
+            }
+            .to_rgb()
+        },
+        |color| (color.r * val, color.g * val, color.b * val),
+    )
+}
+
+/// Returns the context of a frame `elapsed` seconds after the mode started, for tests of effects.
+#[cfg(test)]
+pub fn context(elapsed: f32, matrix: Matrix) -> Context {
+    Context {
+        elapsed: Duration::from_secs_f32(elapsed),
+        delta: Duration::ZERO,
+        matrix,
+    }
+}
+
+/// Linear interpolation between `a` and `b`.
+pub fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    (b - a).mul_add(t, a)
+}
+
+/// Maps a perceived brightness in the range [0-1] to the linear LED output.
+///
+/// This is the inverse of the CIE 1976 lightness formula so that equal steps
+/// in the input look like equal steps in brightness.
+pub fn perceptual(lightness: f32) -> f32 {
+    let l = lightness.clamp(0.0, 1.0) * 100.0;
+
+    if l <= 8.0 {
+        l / 903.3
+    } else {
+        ((l + 16.0) / 116.0).powi(3)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_updates_a_single_effect() -> Result<(), ParamsError> {
+        let mut params = Params::default();
+        let values = HashMap::from([("duration".to_owned(), "600".to_owned())]);
+
+        let sunrise = params.set("sunrise", &values)?;
+
+        assert_eq!(sunrise["duration"], 600.0);
+        assert_eq!(params.get("sunrise")?, sunrise);
+        assert!((params.sunrise.duration - 600.0).abs() < f32::EPSILON);
+        Ok(())
+    }
+
+    #[test]
+    fn set_rejects_unknown_names_without_changes() {
+        let mut params = Params::default();
+        let values = HashMap::from([("speed".to_owned(), "2".to_owned())]);
+
+        assert!(matches!(
+            params.set("sunset", &values),
+            Err(ParamsError::UnknownEffect(_))
+        ));
+        assert!(matches!(
+            params.set("sunrise", &values),
+            Err(ParamsError::UnknownParam(_))
+        ));
+
+        let values = HashMap::from([("duration".to_owned(), "long".to_owned())]);
+        assert!(matches!(
+            params.set("sunrise", &values),
+            Err(ParamsError::Invalid(_))
+        ));
+        assert!((params.sunrise.duration - 1800.0).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn sleep_cant_end_in_sleep() {
+        let mut params = Params::default();
+        let values = HashMap::from([("end_mode".to_owned(), "sleep".to_owned())]);
+
+        assert!(matches!(
+            params.set("sleep", &values),
+            Err(ParamsError::Invalid(_))
+        ));
+        assert!(matches!(
+            params.merge(&serde_json::json!({"sleep": {"end_mode": "SLEEP"}})),
+            Err(ParamsError::Invalid(_))
+        ));
+        assert_eq!(params.sleep.end_mode, Mode::OFF);
+    }
+
+    #[test]
+    fn explicit_colors_are_dimmed_by_val() {
+        let red = Color {
+            r: 1.0,
+            g: 0.0,
+            b: 0.0,
+        };
+
+        assert_eq!(
+            color_or_current(Some(red), 120.0, 1.0, 0.5),
+            (0.5, 0.0, 0.0)
+        );
+        assert_eq!(color_or_current(None, 0.0, 1.0, 0.5), (0.5, 0.0, 0.0));
+    }
+
+    #[test]
+    fn curves_keep_the_end_points() {
+        for curve in [
+            Curve::Linear,
+            Curve::EaseIn,
+            Curve::EaseOut,
+            Curve::EaseInOut,
+            Curve::Perceptual,
+        ] {
+            assert!(curve.apply(0.0).abs() < 1e-6, "{curve:?}");
+            assert!((curve.apply(1.0) - 1.0).abs() < 1e-6, "{curve:?}");
+            assert!((curve.apply(2.0) - 1.0).abs() < 1e-6, "{curve:?}");
+        }
+
+        assert!(Curve::EaseIn.apply(0.5) < 0.5);
+        assert!(Curve::EaseOut.apply(0.5) > 0.5);
+        assert!(Curve::Perceptual.apply(0.5) < 0.2);
+    }
+
+    #[test]
+    fn perceptual_is_monotonic() {
+        assert!(perceptual(0.0).abs() < f32::EPSILON);
+        assert!((perceptual(1.0) - 1.0).abs() < 1e-6);
+
+        let steps: Vec<_> = (0..=100).map(|i| perceptual(i as f32 / 100.0)).collect();
+        assert!(steps.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
@@ -0,0 +1,40 @@
+use serde::{Deserialize, Serialize};
+
+use crate::effects::{noise, palette, Context};
+use crate::pixel::Pixel;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Params {
+    /// Size of the pattern, smaller values give wider curtains.
+    pub scale: f32,
+    /// How fast the pattern changes.
+    pub speed: f32,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            scale: 0.03,
+            speed: 0.05,
+        }
+    }
+}
+
+pub fn render(ctx: &Context, params: &Params, val: f32, frame: &mut [Pixel]) {
+    let t = ctx.elapsed.as_secs_f32() * params.speed;
+
+    for (i, led) in frame.iter_mut().enumerate() {
+        let x = i as f32 * params.scale;
+
+        let color = noise::fbm(t.mul_add(0.5, x), t, 42.0, 2);
+        // a second, finer layer makes the light flicker like curtains
+        let level = noise::fbm(x.mul_add(4.0, -2.0 * t), t * 1.5, 7.0, 2);
+
+        *led = palette::sample(
+            &palette::AURORA,
+            color,
+            val * (level - 0.3).mul_add(2.0, 0.2).clamp(0.0, 1.0),
+        );
+    }
+}
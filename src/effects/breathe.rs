@@ -0,0 +1,87 @@
+use std::f32::consts::{E, TAU};
+
+use serde::{Deserialize, Serialize};
+
+use crate::effects::{lerp, Context};
+use crate::pixel::Pixel;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Wave {
+    #[default]
+    Sine,
+    /// Lingers longer at the low end which looks closer to real breathing.
+    Exponential,
+}
+
+impl Wave {
+    /// Returns the level of the wave in the range [0-1] at `phase` [0-1].
+    fn level(self, phase: f32) -> f32 {
+        // start at the lowest point
+        let sine = phase.mul_add(TAU, -TAU / 4.0).sin();
+
+        match self {
+            Self::Sine => sine.mul_add(0.5, 0.5),
+            Self::Exponential => (sine.exp() - 1.0 / E) / (E - 1.0 / E),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Params {
+    /// Duration of one breath in seconds.
+    pub period: f32,
+    /// Lowest brightness relative to the current value [0-1].
+    pub min: f32,
+    pub wave: Wave,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            period: 4.0,
+            min: 0.1,
+            wave: Wave::default(),
+        }
+    }
+}
+
+pub fn render(ctx: &Context, params: &Params, hue: f32, sat: f32, val: f32, frame: &mut [Pixel]) {
+    let phase = if params.period > 0.0 {
+        (ctx.elapsed.as_secs_f32() / params.period).fract()
+    } else {
+        0.0
+    };
+
+    let level = lerp(params.min.clamp(0.0, 1.0), 1.0, params.wave.level(phase));
+
+    let pixel = Pixel::HSV {
+        h: hue,
+        s: sat,
+        v: val * level,
+    };
+
+    for led in frame {
+        *led = pixel;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waves_start_low_and_peak_halfway() {
+        for wave in [Wave::Sine, Wave::Exponential] {
+            assert!(wave.level(0.0).abs() < 1e-6, "{wave:?}");
+            assert!((wave.level(0.5) - 1.0).abs() < 1e-6, "{wave:?}");
+            assert!(wave.level(1.0).abs() < 1e-6, "{wave:?}");
+        }
+    }
+
+    #[test]
+    fn exponential_lingers_at_the_low_end() {
+        assert!(Wave::Exponential.level(0.25) < Wave::Sine.level(0.25));
+    }
+}
@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+
+use crate::effects::{color_or_current, Context, Direction};
+use crate::pixel::{Color, Pixel};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Params {
+    /// Movement speed in LEDs per second.
+    pub speed: f32,
+    /// Number of lit LEDs per group.
+    pub length: usize,
+    /// Number of dark LEDs between two groups.
+    pub spacing: usize,
+    /// Color of the lit LEDs, the current color is used if unset.
+    pub color: Option<Color>,
+    pub direction: Direction,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            speed: 10.0,
+            length: 1,
+            spacing: 2,
+            color: None,
+            direction: Direction::default(),
+        }
+    }
+}
+
+pub fn render(ctx: &Context, params: &Params, hue: f32, sat: f32, val: f32, frame: &mut [Pixel]) {
+    let (r, g, b) = color_or_current(params.color, hue, sat, val);
+    let lit = Pixel::RGB { r, g, b };
+
+    let period = (params.length + params.spacing).max(1);
+    let offset = (ctx.elapsed.as_secs_f32() * params.speed.max(0.0)) as usize % period;
+
+    let count = frame.len();
+    for i in 0..count {
+        let position = (i + period - offset) % period;
+
+        frame[params.direction.index(i, count)] = if position < params.length {
+            lit
+        } else {
+            Pixel::OFF
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effects::context;
+    use crate::layout::Matrix;
+
+    fn lit(elapsed: f32, params: &Params) -> Vec<bool> {
+        let mut frame = [Pixel::OFF; 6];
+
+        render(
+            &context(elapsed, Matrix::row(6)),
+            params,
+            0.0,
+            1.0,
+            1.0,
+            &mut frame,
+        );
+
+        frame.iter().map(|led| led.to_rgb().0 > 0.0).collect()
+    }
+
+    #[test]
+    fn groups_move_with_the_speed() {
+        let params = Params {
+            speed: 1.0,
+            ..Params::default()
+        };
+
+        assert_eq!(lit(0.0, &params), [true, false, false, true, false, false]);
+        assert_eq!(lit(1.0, &params), [false, true, false, false, true, false]);
+        assert_eq!(lit(3.0, &params), lit(0.0, &params));
+    }
+
+    #[test]
+    fn backward_mirrors_the_strip() {
+        let params = Params {
+            speed: 1.0,
+            direction: Direction::Backward,
+            ..Params::default()
+        };
+
+        assert_eq!(lit(1.0, &params), [false, true, false, false, true, false]);
+        assert_eq!(lit(0.0, &params), [false, false, true, false, false, true]);
+    }
+}
@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+
+use crate::effects::{color_or_current, Context, Direction};
+use crate::pixel::{Color, Pixel};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Params {
+    /// Movement speed in LEDs per second.
+    pub speed: f32,
+    /// Length of the fading tail in LEDs.
+    pub length: f32,
+    /// Color of the comet, the current color is used if unset.
+    pub color: Option<Color>,
+    pub direction: Direction,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            speed: 30.0,
+            length: 15.0,
+            color: None,
+            direction: Direction::default(),
+        }
+    }
+}
+
+/// Brightness of a LED `distance` LEDs behind the head of a tail with `length` LEDs.
+pub fn tail(distance: f32, length: f32) -> f32 {
+    if distance < 0.0 {
+        // smooth the leading edge when the head is between two LEDs
+        (1.0 + distance).max(0.0)
+    } else {
+        (1.0 - distance / length.max(1.0)).max(0.0).powi(2)
+    }
+}
+
+pub fn render(ctx: &Context, params: &Params, hue: f32, sat: f32, val: f32, frame: &mut [Pixel]) {
+    let (r, g, b) = color_or_current(params.color, hue, sat, val);
+
+    let count = frame.len();
+    let head = (ctx.elapsed.as_secs_f32() * params.speed.max(0.0)) % count.max(1) as f32;
+
+    for i in 0..count {
+        let mut distance = head - i as f32;
+        // the tail wraps around the end of the strip
+        if distance < -1.0 {
+            distance += count as f32;
+        }
+
+        let level = tail(distance, params.length);
+
+        frame[params.direction.index(i, count)] = Pixel::RGB {
+            r: r * level,
+            g: g * level,
+            b: b * level,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tail_fades_behind_the_head() {
+        assert!((tail(0.0, 4.0) - 1.0).abs() < f32::EPSILON);
+        assert!((tail(2.0, 4.0) - 0.25).abs() < f32::EPSILON);
+        assert!(tail(4.0, 4.0).abs() < f32::EPSILON);
+        assert!(tail(10.0, 4.0).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn tail_smooths_the_leading_edge() {
+        assert!((tail(-0.25, 4.0) - 0.75).abs() < f32::EPSILON);
+        assert!(tail(-1.5, 4.0).abs() < f32::EPSILON);
+    }
+}
@@ -0,0 +1,265 @@
+use fasteval::{Compiler, Evaler, Instruction, Parser, Slab};
+use serde::{Deserialize, Serialize};
+
+use crate::effects::Context;
+use crate::pixel::Pixel;
+
+/// Expressions as submitted through the API, either for HSV or RGB channels.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum Source {
+    HSV {
+        #[serde(default = "zero")]
+        h: String,
+        #[serde(default = "one")]
+        s: String,
+        #[serde(default = "one")]
+        v: String,
+    },
+    RGB {
+        #[serde(default = "zero")]
+        r: String,
+        #[serde(default = "zero")]
+        g: String,
+        #[serde(default = "zero")]
+        b: String,
+    },
+}
+
+fn zero() -> String {
+    "0".to_owned()
+}
+
+fn one() -> String {
+    "1".to_owned()
+}
+
+struct Expression {
+    slab: Slab,
+    instruction: Instruction,
+}
+
+/// Compiled per-pixel expressions of the CUSTOM mode.
+pub struct Program {
+    source: Source,
+    channels: [Expression; 3],
+}
+
+/// Values the expressions can refer to.
+struct Variables {
+    i: f64,
+    n: f64,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    t: f64,
+    progress: f64,
+    hue: f64,
+    sat: f64,
+    val: f64,
+}
+
+impl Variables {
+    fn lookup(&self, name: &str) -> Option<f64> {
+        match name {
+            "i" => Some(self.i),
+            "n" => Some(self.n),
+            "x" => Some(self.x),
+            "y" => Some(self.y),
+            "width" => Some(self.width),
+            "height" => Some(self.height),
+            "t" => Some(self.t),
+            "progress" => Some(self.progress),
+            "hue" => Some(self.hue),
+            "sat" => Some(self.sat),
+            "val" => Some(self.val),
+            _ => None,
+        }
+    }
+}
+
+impl Expression {
+    fn compile(name: &str, source: &str) -> Result<Self, String> {
+        let mut slab = Slab::new();
+
+        let instruction = Parser::new()
+            .parse(source, &mut slab.ps)
+            .map_err(|err| format!("{name}: {err:?}"))?
+            .from(&slab.ps)
+            .compile(&slab.ps, &mut slab.cs);
+
+        Ok(Self { slab, instruction })
+    }
+
+    fn eval(&self, vars: &Variables) -> Result<f64, fasteval::Error> {
+        let mut ns = |name: &str, _args: Vec<f64>| vars.lookup(name);
+
+        self.instruction.eval(&self.slab, &mut ns)
+    }
+}
+
+impl Program {
+    /// Compiles the expressions and checks them by evaluating them once.
+    pub fn compile(source: Source) -> Result<Self, String> {
+        let channels = match &source {
+            Source::HSV { h, s, v } => [
+                Expression::compile("h", h)?,
+                Expression::compile("s", s)?,
+                Expression::compile("v", v)?,
+            ],
+            Source::RGB { r, g, b } => [
+                Expression::compile("r", r)?,
+                Expression::compile("g", g)?,
+                Expression::compile("b", b)?,
+            ],
+        };
+
+        let program = Self { source, channels };
+
+        // unknown variables only show up during evaluation
+        let vars = Variables {
+            i: 0.0,
+            n: 1.0,
+            x: 0.0,
+            y: 0.0,
+            width: 1.0,
+            height: 1.0,
+            t: 0.0,
+            progress: 0.0,
+            hue: 0.0,
+            sat: 1.0,
+            val: 1.0,
+        };
+        for (channel, name) in program.channels.iter().zip(program.names()) {
+            channel
+                .eval(&vars)
+                .map_err(|err| format!("{name}: {err:?}"))?;
+        }
+
+        Ok(program)
+    }
+
+    pub const fn source(&self) -> &Source {
+        &self.source
+    }
+
+    const fn names(&self) -> [&'static str; 3] {
+        match self.source {
+            Source::HSV { .. } => ["h", "s", "v"],
+            Source::RGB { .. } => ["r", "g", "b"],
+        }
+    }
+
+    fn pixel(&self, vars: &Variables) -> Pixel {
+        let mut values = [0.0; 3];
+        for (value, channel) in values.iter_mut().zip(&self.channels) {
+            *value = channel.eval(vars).unwrap_or(0.0) as f32;
+        }
+
+        match self.source {
+            Source::HSV { .. } => Pixel::HSV {
+                h: values[0],
+                s: values[1],
+                v: values[2],
+            },
+            Source::RGB { .. } => Pixel::RGB {
+                r: values[0],
+                g: values[1],
+                b: values[2],
+            },
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn render(
+    ctx: &Context,
+    program: Option<&Program>,
+    progress: f32,
+    hue: f32,
+    sat: f32,
+    val: f32,
+    frame: &mut [Pixel],
+) {
+    let Some(program) = program else {
+        for led in frame {
+            *led = Pixel::OFF;
+        }
+        return;
+    };
+
+    let mut vars = Variables {
+        i: 0.0,
+        n: frame.len() as f64,
+        x: 0.0,
+        y: 0.0,
+        width: ctx.matrix.width as f64,
+        height: ctx.matrix.height as f64,
+        t: ctx.elapsed.as_secs_f64(),
+        progress: progress as f64,
+        hue: hue as f64,
+        sat: sat as f64,
+        val: val as f64,
+    };
+
+    for (i, led) in frame.iter_mut().enumerate() {
+        let (x, y) = ctx.matrix.position(i);
+        vars.i = i as f64;
+        vars.x = x as f64;
+        vars.y = y as f64;
+        *led = program.pixel(&vars);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effects::context;
+    use crate::layout::Matrix;
+
+    fn rgb(r: &str, g: &str, b: &str) -> Source {
+        Source::RGB {
+            r: r.to_owned(),
+            g: g.to_owned(),
+            b: b.to_owned(),
+        }
+    }
+
+    #[test]
+    fn invalid_expressions_are_rejected() {
+        assert!(Program::compile(rgb("1 +", "0", "0")).is_err());
+
+        let unknown = Program::compile(rgb("0", "speed", "0"));
+        assert!(matches!(unknown, Err(err) if err.starts_with("g:")));
+    }
+
+    #[test]
+    fn missing_channels_use_defaults() -> Result<(), serde_json::Error> {
+        let source: Source = serde_json::from_str(r#"{"h": "0.5"}"#)?;
+        assert!(matches!(&source, Source::HSV { s, v, .. } if s == "1" && v == "1"));
+
+        let source: Source = serde_json::from_str(r#"{"g": "1"}"#)?;
+        assert!(matches!(&source, Source::RGB { r, b, .. } if r == "0" && b == "0"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn expressions_see_the_position() -> Result<(), String> {
+        let program = Program::compile(rgb("i / (n - 1)", "t", "val"))?;
+        let ctx = context(0.5, Matrix::row(3));
+        let mut frame = [Pixel::OFF; 3];
+
+        render(&ctx, Some(&program), 0.0, 0.0, 1.0, 0.25, &mut frame);
+
+        for (i, led) in frame.iter().enumerate() {
+            let (r, g, b) = led.to_rgb();
+            assert!((r - i as f32 / 2.0).abs() < 1e-6);
+            assert!((g - 0.5).abs() < 1e-6);
+            assert!((b - 0.25).abs() < 1e-6);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,7 @@
+use crate::pixel::{Color, Pixel};
+
+pub fn render(colors: &[Color], frame: &mut [Pixel]) {
+    for (led, color) in frame.iter_mut().zip(colors) {
+        *led = color.to_pixel();
+    }
+}
@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+
+use crate::effects::{Context, PixelMemory};
+use crate::pixel::Pixel;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Params {
+    /// How fast the flames cool down, higher values give shorter flames [0-255].
+    pub cooling: f32,
+    /// Chance of a new spark per step, higher values give a more roaring fire [0-255].
+    pub sparking: f32,
+    /// Simulation steps per second.
+    pub speed: f32,
+    /// Let the flames rise from the end of the strip instead of the start.
+    pub reverse: bool,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            cooling: 55.0,
+            sparking: 120.0,
+            speed: 60.0,
+            reverse: false,
+        }
+    }
+}
+
+/// Heat simulation of the fire, one cell per LED.
+#[derive(Default)]
+pub struct Simulation {
+    heat: PixelMemory<f32>,
+    pending: f32,
+}
+
+impl Simulation {
+    fn step(heat: &mut [f32], params: &Params) {
+        let count = heat.len();
+
+        // cool down every cell a little
+        let max_cooling = ((params.cooling.max(0.0) * 10.0) / count as f32 + 2.0) / 255.0;
+        for cell in heat.iter_mut() {
+            *cell = fastrand::f32().mul_add(-max_cooling, *cell).max(0.0);
+        }
+
+        // heat drifts up and diffuses a little
+        for k in (2..count).rev() {
+            heat[k] = 2.0f32.mul_add(heat[k - 2], heat[k - 1]) / 3.0;
+        }
+
+        // randomly ignite new sparks near the bottom
+        if fastrand::f32() * 255.0 < params.sparking {
+            let y = fastrand::usize(..count.min(7));
+            heat[y] = fastrand::f32().mul_add(0.37, heat[y] + 0.63).min(1.0);
+        }
+    }
+}
+
+/// Maps a heat value in the range [0-1] to black, red, yellow and white dimmed to `val`.
+fn heat_color(heat: f32, val: f32) -> Pixel {
+    let scaled = heat.clamp(0.0, 1.0) * 3.0;
+
+    let (r, g, b) = if scaled < 1.0 {
+        (scaled, 0.0, 0.0)
+    } else if scaled < 2.0 {
+        (1.0, scaled - 1.0, 0.0)
+    } else {
+        (1.0, 1.0, scaled - 2.0)
+    };
+
+    Pixel::RGB {
+        r: r * val,
+        g: g * val,
+        b: b * val,
+    }
+}
+
+pub fn render(ctx: &Context, params: &Params, val: f32, sim: &mut Simulation, frame: &mut [Pixel]) {
+    if frame.is_empty() {
+        return;
+    }
+
+    // run as many steps as the configured speed requires, but never stall on a long pause
+    sim.pending = ctx
+        .delta
+        .as_secs_f32()
+        .mul_add(params.speed.max(0.0), sim.pending)
+        .min(10.0);
+    let steps = sim.pending.floor();
+    sim.pending -= steps;
+
+    let count = frame.len();
+    let heat = sim.heat.cells(count);
+    for _ in 0..steps as usize {
+        Simulation::step(heat, params);
+    }
+
+    for (i, heat) in heat.iter().enumerate() {
+        let index = if params.reverse { count - 1 - i } else { i };
+        frame[index] = heat_color(*heat, val);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heat_color_follows_the_palette() {
+        assert_eq!(heat_color(0.0, 1.0).to_rgb(), (0.0, 0.0, 0.0));
+        assert_eq!(heat_color(0.5, 1.0).to_rgb(), (1.0, 0.5, 0.0));
+        assert_eq!(heat_color(1.0, 1.0).to_rgb(), (1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn heat_color_is_dimmed_by_val() {
+        assert_eq!(heat_color(1.0, 0.25).to_rgb(), (0.25, 0.25, 0.25));
+        assert_eq!(heat_color(0.5, 0.0).to_rgb(), (0.0, 0.0, 0.0));
+    }
+}
@@ -0,0 +1,146 @@
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+use crate::effects::{lerp, Context, Direction};
+use crate::pixel::{Color, Pixel};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Params {
+    /// Colors that get spread evenly along the strip.
+    #[serde(deserialize_with = "deserialize_stops")]
+    pub stops: Vec<Color>,
+    /// Scroll speed in LEDs per second, 0 keeps the gradient in place.
+    pub speed: f32,
+    pub direction: Direction,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            stops: vec![
+                Color {
+                    r: 1.0,
+                    g: 0.0,
+                    b: 0.0,
+                },
+                Color {
+                    r: 0.0,
+                    g: 0.0,
+                    b: 1.0,
+                },
+            ],
+            speed: 0.0,
+            direction: Direction::default(),
+        }
+    }
+}
+
+// stops can be passed as a list or as a comma separated string
+fn deserialize_stops<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Color>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        List(Vec<Color>),
+        Text(String),
+    }
+
+    let stops = match Repr::deserialize(deserializer)? {
+        Repr::List(stops) => stops,
+        Repr::Text(text) => text
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map_err(de::Error::custom)?,
+    };
+
+    if stops.is_empty() {
+        return Err(de::Error::custom("at least one color stop is required"));
+    }
+
+    Ok(stops)
+}
+
+pub fn render(ctx: &Context, params: &Params, val: f32, frame: &mut [Pixel]) {
+    let count = frame.len();
+    if count == 0 || params.stops.is_empty() {
+        return;
+    }
+
+    let stops: Vec<[f32; 3]> = params.stops.iter().map(|c| c.to_oklab()).collect();
+
+    // a scrolling gradient wraps around, so it has to blend back into the first stop
+    let scrolling = params.speed != 0.0;
+    let segments = if scrolling {
+        stops.len()
+    } else {
+        stops.len() - 1
+    };
+    let offset = ctx.elapsed.as_secs_f32() * params.speed;
+
+    for i in 0..count {
+        let position = if scrolling {
+            ((i as f32 - offset) / count as f32).rem_euclid(1.0)
+        } else {
+            i as f32 / count.saturating_sub(1).max(1) as f32
+        };
+
+        let scaled = position * segments as f32;
+        let index = (scaled as usize).min(segments.saturating_sub(1));
+        let t = scaled - index as f32;
+
+        let from = stops[index];
+        let to = stops[(index + 1) % stops.len()];
+
+        let color = Color::from_oklab([
+            lerp(from[0], to[0], t),
+            lerp(from[1], to[1], t),
+            lerp(from[2], to[2], t),
+        ]);
+
+        frame[params.direction.index(i, count)] = Pixel::RGB {
+            r: color.r * val,
+            g: color.g * val,
+            b: color.b * val,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effects::context;
+    use crate::layout::Matrix;
+
+    #[test]
+    fn still_gradients_end_on_the_last_stop() {
+        let mut frame = [Pixel::OFF; 5];
+
+        render(
+            &context(0.0, Matrix::row(5)),
+            &Params::default(),
+            0.5,
+            &mut frame,
+        );
+
+        let (r, g, b) = frame[0].to_rgb();
+        assert!((r - 0.5).abs() < 1e-3 && g < 1e-3 && b < 1e-3);
+
+        let (r, g, b) = frame[4].to_rgb();
+        assert!(r < 1e-3 && g < 1e-3 && (b - 0.5).abs() < 1e-3);
+
+        // the midpoint blends into purple
+        let (r, g, b) = frame[2].to_rgb();
+        assert!(r > 0.05 && b > 0.05 && g < r.min(b));
+    }
+
+    #[test]
+    fn stops_are_a_list_or_a_string() -> Result<(), serde_json::Error> {
+        let list: Params = serde_json::from_str(r#"{"stops": ["ff0000", "00ff00", "0000ff"]}"#)?;
+        let text: Params = serde_json::from_str(r#"{"stops": "ff0000,00ff00,0000ff"}"#)?;
+
+        assert_eq!(list.stops, text.stops);
+        assert!(serde_json::from_str::<Params>(r#"{"stops": []}"#).is_err());
+        assert!(serde_json::from_str::<Params>(r#"{"stops": "ff0000,nope"}"#).is_err());
+        Ok(())
+    }
+}
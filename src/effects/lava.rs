@@ -0,0 +1,33 @@
+use serde::{Deserialize, Serialize};
+
+use crate::effects::{noise, palette, Context};
+use crate::pixel::Pixel;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Params {
+    /// Size of the pattern, smaller values give larger blobs.
+    pub scale: f32,
+    /// How fast the pattern changes.
+    pub speed: f32,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            scale: 0.05,
+            speed: 0.1,
+        }
+    }
+}
+
+pub fn render(ctx: &Context, params: &Params, val: f32, frame: &mut [Pixel]) {
+    let t = ctx.elapsed.as_secs_f32() * params.speed;
+
+    for (i, led) in frame.iter_mut().enumerate() {
+        let n = noise::fbm(i as f32 * params.scale, t, 11.3, 3);
+
+        // stretch the contrast so there are dark crusts and glowing spots
+        *led = palette::sample(&palette::LAVA, (n - 0.5).mul_add(1.8, 0.5), val);
+    }
+}
@@ -0,0 +1,115 @@
+// Gradient (Perlin) noise in three dimensions.
+//
+// Gradients are picked by hashing the lattice coordinates instead of using a
+// permutation table so the noise is the same for all coordinates without wrapping.
+
+use crate::effects::lerp;
+
+const fn hash(x: i32, y: i32, z: i32) -> u32 {
+    let mut h = (x as u32).wrapping_mul(0x8da6_b343)
+        ^ (y as u32).wrapping_mul(0xd816_3841)
+        ^ (z as u32).wrapping_mul(0xcb1a_b31f);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0x5bd1_e995);
+    h ^ (h >> 15)
+}
+
+fn gradient(hash: u32, x: f32, y: f32, z: f32) -> f32 {
+    // the 12 edges of a cube, with 4 of them repeated to get 16 cases
+    match hash & 15 {
+        0 | 12 => x + y,
+        1 | 14 => y - x,
+        2 => x - y,
+        3 => -x - y,
+        4 => x + z,
+        5 => z - x,
+        6 => x - z,
+        7 => -x - z,
+        8 => y + z,
+        9 | 13 => z - y,
+        10 => y - z,
+        _ => -y - z,
+    }
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * t.mul_add(t.mul_add(6.0, -15.0), 10.0)
+}
+
+/// Returns coherent noise in the range of roughly [-1, 1].
+pub fn perlin(x: f32, y: f32, z: f32) -> f32 {
+    let (xf, yf, zf) = (x.floor(), y.floor(), z.floor());
+    let (xi, yi, zi) = (xf as i32, yf as i32, zf as i32);
+    let (x, y, z) = (x - xf, y - yf, z - zf);
+    let (fx, fy, fz) = (fade(x), fade(y), fade(z));
+
+    let corner = |dx: i32, dy: i32, dz: i32| {
+        gradient(
+            hash(xi + dx, yi + dy, zi + dz),
+            x - dx as f32,
+            y - dy as f32,
+            z - dz as f32,
+        )
+    };
+
+    lerp(
+        lerp(
+            lerp(corner(0, 0, 0), corner(1, 0, 0), fx),
+            lerp(corner(0, 1, 0), corner(1, 1, 0), fx),
+            fy,
+        ),
+        lerp(
+            lerp(corner(0, 0, 1), corner(1, 0, 1), fx),
+            lerp(corner(0, 1, 1), corner(1, 1, 1), fx),
+            fy,
+        ),
+        fz,
+    )
+}
+
+/// Sums multiple octaves of noise and maps the result into the range [0-1].
+pub fn fbm(x: f32, y: f32, z: f32, octaves: u32) -> f32 {
+    let mut sum = 0.0;
+    let mut amplitude = 1.0;
+    let mut frequency = 1.0;
+    let mut total = 0.0;
+
+    for _ in 0..octaves.max(1) {
+        sum += perlin(x * frequency, y * frequency, z * frequency) * amplitude;
+        total += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+
+    (sum / total).mul_add(0.5, 0.5).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn perlin_is_zero_on_the_lattice() {
+        for (x, y, z) in [(0.0, 0.0, 0.0), (3.0, -2.0, 7.0), (-5.0, 1.0, 0.0)] {
+            assert!(perlin(x, y, z).abs() < f32::EPSILON);
+        }
+    }
+
+    #[test]
+    fn perlin_is_coherent() {
+        let (x, y, z) = (1.3, 4.7, 0.2);
+
+        assert!((perlin(x, y, z) - perlin(x + 0.001, y, z)).abs() < 0.01);
+        assert!((perlin(x, y, z) - perlin(x, y, z + 0.001)).abs() < 0.01);
+    }
+
+    #[test]
+    fn fbm_stays_in_range() {
+        for i in 0..1000 {
+            let t = i as f32 * 0.37;
+            let value = fbm(t, t * 0.5, -t, 4);
+
+            assert!((0.0..=1.0).contains(&value));
+        }
+    }
+}
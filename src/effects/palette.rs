@@ -0,0 +1,76 @@
+use crate::effects::lerp;
+use crate::pixel::{Color, Pixel};
+
+/// Colors placed at positions in the range [0-1].
+pub type Palette = [(f32, Color)];
+
+const fn rgb(r: u8, g: u8, b: u8) -> Color {
+    Color {
+        r: r as f32 / 255.0,
+        g: g as f32 / 255.0,
+        b: b as f32 / 255.0,
+    }
+}
+
+pub const PLASMA: [(f32, Color); 5] = [
+    (0.0, rgb(40, 0, 120)),
+    (0.3, rgb(200, 0, 160)),
+    (0.5, rgb(255, 60, 40)),
+    (0.7, rgb(255, 190, 0)),
+    (1.0, rgb(0, 160, 255)),
+];
+
+pub const LAVA: [(f32, Color); 5] = [
+    (0.0, rgb(0, 0, 0)),
+    (0.35, rgb(90, 0, 0)),
+    (0.6, rgb(255, 20, 0)),
+    (0.8, rgb(255, 110, 0)),
+    (1.0, rgb(255, 220, 60)),
+];
+
+pub const AURORA: [(f32, Color); 5] = [
+    (0.0, rgb(0, 4, 20)),
+    (0.35, rgb(0, 40, 60)),
+    (0.55, rgb(0, 220, 90)),
+    (0.8, rgb(0, 160, 170)),
+    (1.0, rgb(130, 40, 200)),
+];
+
+/// Samples the palette at `t` in the range [0-1] and scales the result by `level`.
+pub fn sample(palette: &Palette, t: f32, level: f32) -> Pixel {
+    let t = t.clamp(0.0, 1.0);
+
+    let upper = palette
+        .iter()
+        .position(|stop| stop.0 >= t)
+        .unwrap_or(palette.len() - 1)
+        .max(1);
+    let (p0, c0) = palette[upper - 1];
+    let (p1, c1) = palette[upper];
+    let f = ((t - p0) / (p1 - p0)).clamp(0.0, 1.0);
+
+    Pixel::RGB {
+        r: lerp(c0.r, c1.r, f) * level,
+        g: lerp(c0.g, c1.g, f) * level,
+        b: lerp(c0.b, c1.b, f) * level,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_blends_between_stops() {
+        let palette = [
+            (0.0, rgb(0, 0, 0)),
+            (0.5, rgb(255, 0, 0)),
+            (1.0, rgb(255, 255, 255)),
+        ];
+
+        assert_eq!(sample(&palette, 0.25, 1.0).to_rgb(), (0.5, 0.0, 0.0));
+        assert_eq!(sample(&palette, 0.5, 1.0).to_rgb(), (1.0, 0.0, 0.0));
+        assert_eq!(sample(&palette, 2.0, 0.5).to_rgb(), (0.5, 0.5, 0.5));
+        assert_eq!(sample(&palette, -1.0, 1.0).to_rgb(), (0.0, 0.0, 0.0));
+    }
+}
@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+use crate::effects::{noise, palette, Context};
+use crate::pixel::Pixel;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Params {
+    /// Size of the pattern, smaller values give larger blobs.
+    pub scale: f32,
+    /// How fast the pattern changes.
+    pub speed: f32,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            scale: 0.08,
+            speed: 0.4,
+        }
+    }
+}
+
+pub fn render(ctx: &Context, params: &Params, val: f32, frame: &mut [Pixel]) {
+    let t = ctx.elapsed.as_secs_f32() * params.speed;
+
+    for (i, led) in frame.iter_mut().enumerate() {
+        let (x, y) = ctx.matrix.position(i);
+        let x = x as f32 * params.scale;
+        let y = y as f32 * params.scale;
+
+        // two layers moving against each other give the typical plasma swirls
+        let n = noise::fbm(x + t, t.mul_add(0.5, y), 0.0, 2)
+            + noise::fbm(t.mul_add(-0.7, x), y + 3.7, t * 0.3, 2);
+
+        *led = palette::sample(&palette::PLASMA, (n - 0.5).clamp(0.0, 1.0), val);
+    }
+}
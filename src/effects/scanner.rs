@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+
+use crate::effects::{color_or_current, comet::tail, Context, Direction};
+use crate::pixel::{Color, Pixel};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Params {
+    /// Movement speed in LEDs per second.
+    pub speed: f32,
+    /// Length of the fading tail in LEDs.
+    pub length: f32,
+    /// Color of the scanner, the current color is used if unset. Red by default.
+    pub color: Option<Color>,
+    /// Direction of the first sweep.
+    pub direction: Direction,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            speed: 20.0,
+            length: 6.0,
+            color: Some(Color {
+                r: 1.0,
+                g: 0.0,
+                b: 0.0,
+            }),
+            direction: Direction::default(),
+        }
+    }
+}
+
+pub fn render(ctx: &Context, params: &Params, hue: f32, sat: f32, val: f32, frame: &mut [Pixel]) {
+    let (r, g, b) = color_or_current(params.color, hue, sat, val);
+
+    let count = frame.len();
+    let span = count.saturating_sub(1).max(1) as f32;
+    let travel = (ctx.elapsed.as_secs_f32() * params.speed.max(0.0)) % (2.0 * span);
+
+    // bounce back and forth, the tail always trails behind the head
+    let (head, returning) = if travel < span {
+        (travel, false)
+    } else {
+        (2.0f32.mul_add(span, -travel), true)
+    };
+
+    for i in 0..count {
+        let distance = if returning {
+            i as f32 - head
+        } else {
+            head - i as f32
+        };
+
+        let level = tail(distance, params.length);
+
+        frame[params.direction.index(i, count)] = Pixel::RGB {
+            r: r * level,
+            g: g * level,
+            b: b * level,
+        };
+    }
+}
@@ -0,0 +1,328 @@
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use rhai::{Array, CallFnOptions, Dynamic, Engine, Scope, AST};
+use serde::{Deserialize, Serialize};
+
+use crate::effects::Context;
+use crate::pixel::Pixel;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Params {
+    /// Name of the script to run, without the `.rhai` extension.
+    pub name: String,
+}
+
+/// Persistent state of the running script.
+pub struct Memory {
+    name: String,
+    this: Dynamic,
+    failing: bool,
+}
+
+/// Loads scripts from a directory and runs them in a sandboxed engine.
+pub struct Runner {
+    engine: Engine,
+    deadline: Rc<Cell<Instant>>,
+    budget: Duration,
+    dir: Option<PathBuf>,
+    scripts: BTreeMap<String, AST>,
+}
+
+fn number(value: &Dynamic) -> f32 {
+    value
+        .as_float()
+        .or_else(|_| value.as_int().map(|int| int as f64))
+        .unwrap_or(0.0) as f32
+}
+
+/// Returns an engine with the limits and functions available to scripts.
+fn sandbox() -> Engine {
+    let mut engine = Engine::new();
+
+    engine
+        .set_max_call_levels(32)
+        .set_max_expr_depths(64, 32)
+        .set_max_string_size(4096)
+        .set_max_array_size(16 * 1024)
+        .set_max_map_size(1024)
+        .on_print(|text| debug!("script: {text}"))
+        .on_debug(|text, _, pos| debug!("script {pos}: {text}"));
+
+    engine
+        .register_type_with_name::<Pixel>("Color")
+        .register_fn("rgb", |r: Dynamic, g: Dynamic, b: Dynamic| Pixel::RGB {
+            r: number(&r),
+            g: number(&g),
+            b: number(&b),
+        })
+        .register_fn("hsv", |h: Dynamic, s: Dynamic, v: Dynamic| Pixel::HSV {
+            h: number(&h),
+            s: number(&s),
+            v: number(&v),
+        });
+
+    engine
+}
+
+/// Compiles a script without running it, returns the error if it can't be loaded.
+pub fn check(source: &str) -> Result<(), String> {
+    sandbox()
+        .compile(source)
+        .map(|_| ())
+        .map_err(|err| err.to_string())
+}
+
+impl Runner {
+    pub fn new(dir: Option<PathBuf>, budget: Duration) -> Self {
+        let deadline = Rc::new(Cell::new(Instant::now()));
+
+        let mut engine = sandbox();
+
+        // abort scripts that take longer than their budget
+        let progress_deadline = Rc::clone(&deadline);
+        engine.on_progress(move |_| {
+            (Instant::now() > progress_deadline.get()).then(|| "time budget exceeded".into())
+        });
+
+        Self {
+            engine,
+            deadline,
+            budget,
+            dir,
+            scripts: BTreeMap::new(),
+        }
+    }
+
+    /// (Re)loads all scripts and returns the load error of every script, if any.
+    pub fn load(&mut self) -> BTreeMap<String, Option<String>> {
+        self.scripts.clear();
+
+        let mut status = BTreeMap::new();
+
+        let Some(dir) = &self.dir else {
+            return status;
+        };
+
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                warn!("unable to read script directory {}: {err}", dir.display());
+                return status;
+            }
+        };
+
+        for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+            if path.extension().is_none_or(|ext| ext != "rhai") {
+                continue;
+            }
+
+            let Some(name) = path.file_stem().map(|s| s.to_string_lossy().into_owned()) else {
+                continue;
+            };
+
+            match self.engine.compile_file(path) {
+                Ok(ast) => {
+                    debug!("loaded script {name}");
+                    self.scripts.insert(name.clone(), ast);
+                    status.insert(name, None);
+                }
+                Err(err) => {
+                    warn!("unable to load script {name}: {err}");
+                    status.insert(name, Some(err.to_string()));
+                }
+            }
+        }
+
+        info!("loaded {} scripts", self.scripts.len());
+
+        status
+    }
+
+    fn call<T: Clone + 'static>(
+        &self,
+        ast: &AST,
+        this: &mut Dynamic,
+        name: &str,
+        args: impl rhai::FuncArgs,
+    ) -> Result<T, String> {
+        self.deadline.set(Instant::now() + self.budget);
+
+        self.engine
+            .call_fn_with_options(
+                CallFnOptions::new().bind_this_ptr(this).eval_ast(false),
+                &mut Scope::new(),
+                ast,
+                name,
+                args,
+            )
+            .map_err(|err| err.to_string())
+    }
+
+    pub fn render(
+        &self,
+        ctx: &Context,
+        params: &Params,
+        memory: &mut Option<Memory>,
+        frame: &mut [Pixel],
+    ) {
+        for led in frame.iter_mut() {
+            *led = Pixel::OFF;
+        }
+
+        let Some(ast) = self.scripts.get(&params.name) else {
+            return;
+        };
+
+        let count = i64::try_from(frame.len()).unwrap_or(i64::MAX);
+
+        // (re)initialize the script state when the script changes
+        if memory
+            .as_ref()
+            .is_none_or(|memory| memory.name != params.name)
+        {
+            let mut this = Dynamic::UNIT;
+            let has_init = ast.iter_functions().any(|f| f.name == "init");
+
+            if has_init {
+                match self.call::<Dynamic>(ast, &mut this, "init", (count,)) {
+                    Ok(state) => this = state,
+                    Err(err) => warn!("script {} failed to initialize: {err}", params.name),
+                }
+            }
+
+            *memory = Some(Memory {
+                name: params.name.clone(),
+                this,
+                failing: false,
+            });
+        }
+
+        let Some(memory) = memory else {
+            return;
+        };
+
+        let result = self.call::<Array>(
+            ast,
+            &mut memory.this,
+            "render",
+            (count, ctx.elapsed.as_secs_f64(), ctx.delta.as_secs_f64()),
+        );
+
+        match result {
+            Ok(leds) => {
+                memory.failing = false;
+
+                for (led, value) in frame.iter_mut().zip(leds) {
+                    *led = value.try_cast::<Pixel>().unwrap_or(Pixel::OFF);
+                }
+            }
+            Err(err) => {
+                // only report the first of consecutive failures to not flood the log
+                if !memory.failing {
+                    warn!("script {} failed to render: {err}", params.name);
+                }
+                memory.failing = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+    use crate::effects::context;
+    use crate::layout::Matrix;
+    use crate::storage::TempDir;
+
+    /// Returns a runner for the given scripts, they are removed when the directory is dropped.
+    fn runner(test: &str, scripts: &[(&str, &str)]) -> io::Result<(TempDir, Runner)> {
+        let dir = TempDir::new(&format!("script-{test}"))?;
+
+        for (name, source) in scripts {
+            fs::write(dir.path().join(format!("{name}.rhai")), source)?;
+        }
+
+        let runner = Runner::new(Some(dir.path().to_owned()), Duration::from_millis(50));
+        Ok((dir, runner))
+    }
+
+    fn render(runner: &Runner, name: &str, memory: &mut Option<Memory>, count: usize) -> Vec<f32> {
+        let params = Params {
+            name: name.to_owned(),
+        };
+        let mut frame = vec![Pixel::RGB {
+            r: 1.0,
+            g: 1.0,
+            b: 1.0,
+        }];
+        frame.resize(count, frame[0]);
+
+        runner.render(
+            &context(0.0, Matrix::row(count)),
+            &params,
+            memory,
+            &mut frame,
+        );
+
+        frame.iter().map(|led| led.to_rgb().0).collect()
+    }
+
+    #[test]
+    fn scripts_keep_their_state() -> io::Result<()> {
+        let (_dir, mut runner) = runner(
+            "state",
+            &[(
+                "counter",
+                "fn init(count) { #{ frames: 0 } }
+                 fn render(count, t, dt) {
+                     this.frames += 1;
+                     let leds = [];
+                     for i in 0..count { leds.push(rgb(this.frames / 4.0, 0, 0)); }
+                     leds
+                 }",
+            )],
+        )?;
+        let status = runner.load();
+        assert!(matches!(status.get("counter"), Some(None)));
+
+        let mut memory = None;
+        assert_eq!(render(&runner, "counter", &mut memory, 2), [0.25, 0.25]);
+        assert_eq!(render(&runner, "counter", &mut memory, 2), [0.5, 0.5]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn broken_scripts_turn_leds_off() -> io::Result<()> {
+        let (_dir, mut runner) = runner(
+            "broken",
+            &[
+                ("syntax", "fn render(count, t, dt) {"),
+                ("endless", "fn render(count, t, dt) { loop {} }"),
+            ],
+        )?;
+        let status = runner.load();
+        assert!(matches!(status.get("syntax"), Some(Some(_))));
+
+        let mut memory = None;
+        assert_eq!(render(&runner, "syntax", &mut memory, 1), [0.0]);
+        assert_eq!(render(&runner, "endless", &mut memory, 1), [0.0]);
+        assert!(memory.is_some_and(|memory| memory.failing));
+
+        Ok(())
+    }
+
+    #[test]
+    fn scripts_are_checked_before_saving() {
+        assert!(check("fn render(count, t, dt) { [rgb(1, 0, 0)] }").is_ok());
+        assert!(check("fn render(count, t, dt) {").is_err());
+    }
+}
@@ -0,0 +1,468 @@
+use std::io::Cursor;
+
+use image::codecs::gif::GifDecoder;
+use image::imageops::{self, FilterType};
+use image::{AnimationDecoder, ImageDecoder, ImageFormat, ImageReader, Limits, Rgba, RgbaImage};
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+use crate::effects::Context;
+use crate::pixel::{Color, Pixel};
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Playback {
+    /// Play the frames once and keep showing the last frame.
+    Once,
+    #[default]
+    Loop,
+    /// Play the frames forwards and backwards.
+    PingPong,
+}
+
+/// How the pixels of an image are turned into frames.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Layout {
+    /// Every row is a frame, played from top to bottom.
+    #[default]
+    Rows,
+    /// Every column is a frame, played from left to right.
+    Columns,
+}
+
+/// An animation made up of frames that get played one after another.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sequence {
+    #[serde(deserialize_with = "deserialize_frames")]
+    pub frames: Vec<Vec<Color>>,
+    /// Duration of the frames in seconds, the last duration applies to all following frames.
+    #[serde(
+        default = "default_durations",
+        deserialize_with = "deserialize_durations"
+    )]
+    pub durations: Vec<f32>,
+    #[serde(default)]
+    pub playback: Playback,
+}
+
+fn default_durations() -> Vec<f32> {
+    vec![0.1]
+}
+
+fn deserialize_frames<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Vec<Color>>, D::Error> {
+    let frames = Vec::<Vec<Color>>::deserialize(deserializer)?;
+
+    if frames.is_empty() {
+        return Err(de::Error::custom("at least one frame is required"));
+    }
+
+    Ok(frames)
+}
+
+fn deserialize_durations<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<f32>, D::Error> {
+    let durations = Vec::<f32>::deserialize(deserializer)?;
+
+    if durations.is_empty() || durations.iter().any(|d| !d.is_finite() || *d <= 0.0) {
+        return Err(de::Error::custom("durations need to be positive"));
+    }
+
+    Ok(durations)
+}
+
+impl Sequence {
+    /// Parses a CSV table where every row is a frame and every cell a hex color.
+    pub fn from_csv(text: &str, duration: f32, playback: Playback) -> Result<Self, String> {
+        let frames: Vec<Vec<Color>> = text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                line.split([',', ';'])
+                    .map(|cell| cell.trim().trim_matches('"'))
+                    .filter(|cell| !cell.is_empty())
+                    .map(str::parse)
+                    .collect::<Result<_, _>>()
+            })
+            .collect::<Result<_, _>>()?;
+
+        if frames.is_empty() {
+            return Err("at least one frame is required".to_owned());
+        }
+
+        if !duration.is_finite() || duration <= 0.0 {
+            return Err("durations need to be positive".to_owned());
+        }
+
+        Ok(Self {
+            frames,
+            durations: vec![duration],
+            playback,
+        })
+    }
+
+    /// Converts a PNG or GIF image into a sequence with `count` LEDs per frame.
+    ///
+    /// Still images are scaled so that every row (or column) becomes a frame. The frames of an
+    /// animated GIF are scaled down to a single row (or column) each and keep their delays.
+    pub fn from_image(
+        data: &[u8],
+        format: ImageFormat,
+        layout: Layout,
+        duration: f32,
+        playback: Playback,
+        count: usize,
+    ) -> Result<Self, String> {
+        if !duration.is_finite() || duration <= 0.0 {
+            return Err("durations need to be positive".to_owned());
+        }
+
+        let count = u32::try_from(count)
+            .ok()
+            .filter(|count| *count > 0)
+            .ok_or_else(|| "no LEDs to scale the image to".to_owned())?;
+
+        let images = decode(data, format)?;
+
+        if images.len() == 1 {
+            let (image, _) = &images[0];
+
+            let frames = match layout {
+                Layout::Rows => {
+                    let image =
+                        imageops::resize(image, count, image.height(), FilterType::Triangle);
+                    (0..image.height())
+                        .map(|y| {
+                            (0..count)
+                                .map(|x| to_color(*image.get_pixel(x, y)))
+                                .collect()
+                        })
+                        .collect()
+                }
+                Layout::Columns => {
+                    let image = imageops::resize(image, image.width(), count, FilterType::Triangle);
+                    (0..image.width())
+                        .map(|x| {
+                            (0..count)
+                                .map(|y| to_color(*image.get_pixel(x, y)))
+                                .collect()
+                        })
+                        .collect()
+                }
+            };
+
+            return Ok(Self {
+                frames,
+                durations: vec![duration],
+                playback,
+            });
+        }
+
+        let (width, height) = match layout {
+            Layout::Rows => (count, 1),
+            Layout::Columns => (1, count),
+        };
+
+        let mut frames = Vec::with_capacity(images.len());
+        let mut durations = Vec::with_capacity(images.len());
+
+        for (image, delay) in &images {
+            let image = imageops::resize(image, width, height, FilterType::Triangle);
+
+            frames.push(image.pixels().copied().map(to_color).collect());
+            // GIFs without a delay fall back to the requested duration like browsers do
+            durations.push(delay.filter(|delay| *delay > 0.0).unwrap_or(duration));
+        }
+
+        Ok(Self {
+            frames,
+            durations,
+            playback,
+        })
+    }
+
+    fn duration(&self, frame: usize) -> f32 {
+        self.durations
+            .get(frame)
+            .or_else(|| self.durations.last())
+            .copied()
+            .unwrap_or(0.1)
+    }
+
+    fn total(&self) -> f32 {
+        (0..self.frames.len()).map(|i| self.duration(i)).sum()
+    }
+
+    /// Returns the index of the frame that is shown `time` seconds into a single playthrough.
+    fn frame_at(&self, time: f32) -> usize {
+        let mut end = 0.0;
+
+        for i in 0..self.frames.len() {
+            end += self.duration(i);
+
+            if time < end {
+                return i;
+            }
+        }
+
+        self.frames.len() - 1
+    }
+
+    /// Returns whether the sequence finished playing.
+    pub fn finished(&self, ctx: &Context) -> bool {
+        matches!(self.playback, Playback::Once) && ctx.elapsed.as_secs_f32() >= self.total()
+    }
+}
+
+/// Decodes all frames of an image together with their delays in seconds.
+fn decode(data: &[u8], format: ImageFormat) -> Result<Vec<(RgbaImage, Option<f32>)>, String> {
+    // keep malicious images from exhausting the memory
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(4096);
+    limits.max_image_height = Some(4096);
+    limits.max_alloc = Some(64 * 1024 * 1024);
+
+    let frames = if format == ImageFormat::Gif {
+        let mut decoder = GifDecoder::new(Cursor::new(data)).map_err(|err| err.to_string())?;
+        decoder.set_limits(limits).map_err(|err| err.to_string())?;
+
+        decoder
+            .into_frames()
+            .take(MAX_IMAGE_FRAMES + 1)
+            .map(|frame| {
+                frame.map(|frame| {
+                    let (numer, denom) = frame.delay().numer_denom_ms();
+                    let delay = numer as f32 / denom.max(1) as f32 / 1000.0;
+
+                    (frame.into_buffer(), Some(delay))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| err.to_string())?
+    } else {
+        let mut reader = ImageReader::with_format(Cursor::new(data), format);
+        reader.limits(limits);
+
+        vec![(
+            reader.decode().map_err(|err| err.to_string())?.into_rgba8(),
+            None,
+        )]
+    };
+
+    if frames.is_empty() || frames.len() > MAX_IMAGE_FRAMES {
+        return Err(format!(
+            "images need between 1 and {MAX_IMAGE_FRAMES} frames"
+        ));
+    }
+
+    Ok(frames)
+}
+
+const MAX_IMAGE_FRAMES: usize = 1000;
+
+// LEDs can't be transparent, so transparent pixels fade to black
+fn to_color(pixel: Rgba<u8>) -> Color {
+    let [r, g, b, a] = pixel.0;
+    let alpha = a as f32 / 255.0;
+
+    Color {
+        r: r as f32 / 255.0 * alpha,
+        g: g as f32 / 255.0 * alpha,
+        b: b as f32 / 255.0 * alpha,
+    }
+}
+
+pub fn render(ctx: &Context, sequence: Option<&Sequence>, frame: &mut [Pixel]) {
+    for led in frame.iter_mut() {
+        *led = Pixel::OFF;
+    }
+
+    let Some(sequence) = sequence else {
+        return;
+    };
+
+    let total = sequence.total();
+    let time = ctx.elapsed.as_secs_f32();
+
+    let index = match sequence.playback {
+        Playback::Once => sequence.frame_at(time),
+        Playback::Loop => sequence.frame_at(time % total),
+        Playback::PingPong => {
+            let time = time % (2.0 * total);
+
+            if time < total {
+                sequence.frame_at(time)
+            } else {
+                sequence.frame_at(2.0f32.mul_add(total, -time))
+            }
+        }
+    };
+
+    for (led, color) in frame.iter_mut().zip(&sequence.frames[index]) {
+        *led = color.to_pixel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effects::context;
+    use crate::layout::Matrix;
+
+    fn sequence(playback: Playback) -> Result<Sequence, serde_json::Error> {
+        serde_json::from_str(&format!(
+            r#"{{
+                "frames": [["ff0000"], ["00ff00"], ["0000ff"]],
+                "durations": [1.0, 0.5],
+                "playback": "{}"
+            }}"#,
+            match playback {
+                Playback::Once => "once",
+                Playback::Loop => "loop",
+                Playback::PingPong => "ping_pong",
+            }
+        ))
+    }
+
+    /// Returns the index of the channel that is lit at `elapsed` seconds.
+    fn shown(sequence: &Sequence, elapsed: f32) -> Option<usize> {
+        let mut frame = [Pixel::OFF];
+        render(
+            &context(elapsed, Matrix::row(1)),
+            Some(sequence),
+            &mut frame,
+        );
+
+        match frame[0].to_rgb() {
+            (r, _, _) if r > 0.5 => Some(0),
+            (_, g, _) if g > 0.5 => Some(1),
+            (_, _, b) if b > 0.5 => Some(2),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn last_duration_applies_to_following_frames() -> Result<(), serde_json::Error> {
+        let sequence = sequence(Playback::Loop)?;
+
+        assert!((sequence.total() - 2.0).abs() < 1e-6);
+        assert_eq!(shown(&sequence, 0.9), Some(0));
+        assert_eq!(shown(&sequence, 1.2), Some(1));
+        assert_eq!(shown(&sequence, 1.7), Some(2));
+        assert_eq!(shown(&sequence, 2.1), Some(0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn playback_modes() -> Result<(), serde_json::Error> {
+        let once = sequence(Playback::Once)?;
+        assert_eq!(shown(&once, 5.0), Some(2));
+        assert!(!once.finished(&context(1.9, Matrix::row(1))));
+        assert!(once.finished(&context(2.0, Matrix::row(1))));
+
+        let ping_pong = sequence(Playback::PingPong)?;
+        assert_eq!(shown(&ping_pong, 1.7), Some(2));
+        assert_eq!(shown(&ping_pong, 2.7), Some(1));
+        assert_eq!(shown(&ping_pong, 3.5), Some(0));
+        assert!(!ping_pong.finished(&context(10.0, Matrix::row(1))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_sequences_are_rejected() {
+        assert!(serde_json::from_str::<Sequence>(r#"{"frames": []}"#).is_err());
+        assert!(
+            serde_json::from_str::<Sequence>(r#"{"frames": [["ff0000"]], "durations": [0]}"#)
+                .is_err()
+        );
+        assert!(serde_json::from_str::<Sequence>(r#"{"frames": [["red"]]}"#).is_err());
+    }
+
+    #[test]
+    fn csv_rows_become_frames() -> Result<(), String> {
+        let sequence = Sequence::from_csv(
+            "ff0000, 00ff00\n\n\"0000ff\";#ffffff\n",
+            0.2,
+            Playback::Once,
+        )?;
+
+        assert_eq!(sequence.frames.len(), 2);
+        assert_eq!(sequence.frames[1][1].to_string(), "ffffff");
+        assert!((sequence.duration(5) - 0.2).abs() < 1e-6);
+
+        assert!(Sequence::from_csv("", 0.2, Playback::Once).is_err());
+        assert!(Sequence::from_csv("ff0000", 0.0, Playback::Once).is_err());
+        assert!(Sequence::from_csv("ff00", 0.2, Playback::Once).is_err());
+
+        Ok(())
+    }
+
+    fn png(image: &RgbaImage) -> Result<Vec<u8>, image::ImageError> {
+        let mut data = Cursor::new(Vec::new());
+        image.write_to(&mut data, ImageFormat::Png)?;
+
+        Ok(data.into_inner())
+    }
+
+    #[test]
+    fn image_rows_become_frames() -> Result<(), Box<dyn std::error::Error>> {
+        let image = RgbaImage::from_fn(2, 3, |_, y| match y {
+            0 => Rgba([255, 0, 0, 255]),
+            1 => Rgba([0, 255, 0, 255]),
+            _ => Rgba([0, 0, 255, 0]),
+        });
+        let data = png(&image)?;
+
+        let rows = Sequence::from_image(
+            &data,
+            ImageFormat::Png,
+            Layout::Rows,
+            0.5,
+            Playback::Loop,
+            4,
+        )?;
+        assert_eq!(rows.frames.len(), 3);
+        assert!(rows.frames.iter().all(|frame| frame.len() == 4));
+        assert_eq!(rows.frames[1][3].to_string(), "00ff00");
+        // transparent pixels turn black
+        assert_eq!(rows.frames[2][0].to_string(), "000000");
+
+        let columns = Sequence::from_image(
+            &data,
+            ImageFormat::Png,
+            Layout::Columns,
+            0.5,
+            Playback::Loop,
+            3,
+        )?;
+        assert_eq!(columns.frames.len(), 2);
+        assert_eq!(columns.frames[0][0].to_string(), "ff0000");
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_images_are_rejected() -> Result<(), image::ImageError> {
+        let data = png(&RgbaImage::new(1, 1))?;
+
+        let import = |data: &[u8], duration, count| {
+            Sequence::from_image(
+                data,
+                ImageFormat::Png,
+                Layout::Rows,
+                duration,
+                Playback::Loop,
+                count,
+            )
+        };
+
+        assert!(import(&data, 0.1, 1).is_ok());
+        assert!(import(&data, 0.1, 0).is_err());
+        assert!(import(&data, -1.0, 1).is_err());
+        assert!(import(b"not an image", 0.1, 1).is_err());
+
+        Ok(())
+    }
+}
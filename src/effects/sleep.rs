@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+
+use crate::effects::{Context, Curve};
+use crate::pixel::Pixel;
+use crate::state::Mode;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Style {
+    /// Fade out the current color.
+    Color,
+    /// Fade out a slowly moving rainbow while desaturating it.
+    #[default]
+    Rainbow,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Params {
+    /// Duration of the fade in seconds.
+    pub duration: f32,
+    pub curve: Curve,
+    pub style: Style,
+    /// Mode to switch to once the fade is done.
+    pub end_mode: Mode,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            duration: 300.0,
+            curve: Curve::default(),
+            style: Style::default(),
+            end_mode: Mode::OFF,
+        }
+    }
+}
+
+/// Returns the progress of the fade in the range [0-1].
+pub fn progress(ctx: &Context, params: &Params) -> f32 {
+    if params.duration <= 0.0 {
+        return 1.0;
+    }
+
+    (ctx.elapsed.as_secs_f32() / params.duration).clamp(0.0, 1.0)
+}
+
+pub fn render(ctx: &Context, params: &Params, hue: f32, sat: f32, val: f32, frame: &mut [Pixel]) {
+    let progress = progress(ctx, params);
+    let level = params.curve.apply(1.0 - progress);
+
+    match params.style {
+        Style::Color => {
+            let pixel = Pixel::HSV {
+                h: hue,
+                s: sat,
+                v: val * level,
+            };
+
+            for led in frame {
+                *led = pixel;
+            }
+        }
+        Style::Rainbow => {
+            let sleep_sat = progress.mul_add(-(sat / 2.0), sat);
+            let offset = ctx.elapsed.as_secs_f32() * 20.0;
+
+            for (i, led) in frame.iter_mut().enumerate() {
+                *led = Pixel::HSV {
+                    h: (offset + i as f32) * (360.0 / 150.0),
+                    s: sleep_sat,
+                    v: val * level,
+                };
+            }
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+
+use crate::effects::{lerp, twinkle::Cell, Context, PixelMemory};
+use crate::pixel::Pixel;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Params {
+    /// Chance per second of every LED to sparkle.
+    pub density: f32,
+    /// Fade out speed in full fades per second.
+    pub speed: f32,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            density: 0.05,
+            speed: 4.0,
+        }
+    }
+}
+
+pub fn render(
+    ctx: &Context,
+    params: &Params,
+    cells: &mut PixelMemory<Cell>,
+    hue: f32,
+    sat: f32,
+    val: f32,
+    frame: &mut [Pixel],
+) {
+    let (r, g, b) = Pixel::HSV {
+        h: hue,
+        s: sat,
+        v: val,
+    }
+    .to_rgb();
+
+    // sparkles are white at the current value, so they dim with it
+    for (cell, led) in cells.cells(frame.len()).iter_mut().zip(frame) {
+        cell.advance(ctx, params.density, params.speed, false);
+
+        *led = Pixel::RGB {
+            r: lerp(r, val, cell.level),
+            g: lerp(g, val, cell.level),
+            b: lerp(b, val, cell.level),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::effects::context;
+    use crate::layout::Matrix;
+
+    fn ctx(delta: f32) -> Context {
+        Context {
+            delta: Duration::from_secs_f32(delta),
+            ..context(0.0, Matrix::row(2))
+        }
+    }
+
+    #[test]
+    fn sparkles_stay_within_the_value() {
+        let params = Params {
+            density: 0.0,
+            speed: 0.0,
+        };
+        let mut cells = PixelMemory::<Cell>::default();
+        cells.cells(2)[0].level = 1.0;
+        let mut frame = [Pixel::OFF; 2];
+
+        render(&ctx(0.1), &params, &mut cells, 0.0, 1.0, 0.2, &mut frame);
+
+        let (r, g, b) = frame[0].to_rgb();
+        assert!((r - 0.2).abs() < 1e-6 && (g - 0.2).abs() < 1e-6 && (b - 0.2).abs() < 1e-6);
+
+        let (r, g, b) = frame[1].to_rgb();
+        assert!((r - 0.2).abs() < 1e-6 && g.abs() < 1e-6 && b.abs() < 1e-6);
+    }
+}
@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+
+use crate::effects::{lerp, perceptual, Context};
+use crate::pixel::Pixel;
+
+/// Color stops of the sunrise as (position, r, g, b).
+const STOPS: [(f32, f32, f32, f32); 5] = [
+    (0.0, 0.55, 0.0, 0.0),
+    (0.3, 1.0, 0.12, 0.0),
+    (0.55, 1.0, 0.4, 0.03),
+    (0.8, 1.0, 0.68, 0.3),
+    (1.0, 1.0, 0.84, 0.62),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Params {
+    /// Duration of the sunrise in seconds.
+    pub duration: f32,
+    /// Brightness reached at the end of the sunrise [0-1].
+    pub brightness: f32,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            duration: 1800.0,
+            brightness: 1.0,
+        }
+    }
+}
+
+/// Returns the progress of the sunrise in the range [0-1].
+pub fn progress(ctx: &Context, params: &Params) -> f32 {
+    if params.duration <= 0.0 {
+        return 1.0;
+    }
+
+    (ctx.elapsed.as_secs_f32() / params.duration).clamp(0.0, 1.0)
+}
+
+pub fn render(ctx: &Context, params: &Params, val: f32, frame: &mut [Pixel]) {
+    let progress = progress(ctx, params);
+
+    let upper = STOPS
+        .iter()
+        .position(|stop| stop.0 >= progress)
+        .unwrap_or(STOPS.len() - 1)
+        .max(1);
+    let (p0, r0, g0, b0) = STOPS[upper - 1];
+    let (p1, r1, g1, b1) = STOPS[upper];
+    let t = (progress - p0) / (p1 - p0);
+
+    // never start completely dark so the first step isn't a visible jump
+    let level = perceptual(lerp(0.02, 1.0, progress)) * params.brightness.clamp(0.0, 1.0) * val;
+
+    let pixel = Pixel::RGB {
+        r: lerp(r0, r1, t) * level,
+        g: lerp(g0, g1, t) * level,
+        b: lerp(b0, b1, t) * level,
+    };
+
+    for led in frame {
+        *led = pixel;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effects::context;
+    use crate::layout::Matrix;
+
+    #[test]
+    fn sunrise_brightens_from_red_to_warm_white() {
+        let params = Params::default();
+        let mut frame = [Pixel::OFF];
+
+        render(&context(0.0, Matrix::row(1)), &params, 1.0, &mut frame);
+        let (r, g, b) = frame[0].to_rgb();
+        assert!(r > 0.0 && g.abs() < f32::EPSILON && b.abs() < f32::EPSILON);
+
+        let end = context(params.duration, Matrix::row(1));
+        render(&end, &params, 1.0, &mut frame);
+        let (r, g, b) = frame[0].to_rgb();
+        assert!((r - 1.0).abs() < 1e-6 && g > 0.8 && b > 0.6);
+
+        render(&end, &params, 0.5, &mut frame);
+        let (r, _, _) = frame[0].to_rgb();
+        assert!((r - 0.5).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,226 @@
+use serde::{Deserialize, Serialize};
+
+use crate::effects::{color_or_current, Context};
+use crate::pixel::{Color, Pixel};
+
+const GLYPH_WIDTH: usize = 5;
+const GLYPH_HEIGHT: usize = 7;
+/// Columns per character including the gap to the next one.
+const ADVANCE: usize = GLYPH_WIDTH + 1;
+
+/// 5x7 font for the printable ASCII characters, one byte per column with the top row in bit 0.
+const FONT: [[u8; GLYPH_WIDTH]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x00, 0x00, 0x5f, 0x00, 0x00], // !
+    [0x00, 0x07, 0x00, 0x07, 0x00], // "
+    [0x14, 0x7f, 0x14, 0x7f, 0x14], // #
+    [0x24, 0x2a, 0x7f, 0x2a, 0x12], // $
+    [0x23, 0x13, 0x08, 0x64, 0x62], // %
+    [0x36, 0x49, 0x55, 0x22, 0x50], // &
+    [0x00, 0x05, 0x03, 0x00, 0x00], // '
+    [0x00, 0x1c, 0x22, 0x41, 0x00], // (
+    [0x00, 0x41, 0x22, 0x1c, 0x00], // )
+    [0x14, 0x08, 0x3e, 0x08, 0x14], // *
+    [0x08, 0x08, 0x3e, 0x08, 0x08], // +
+    [0x00, 0x50, 0x30, 0x00, 0x00], // ,
+    [0x08, 0x08, 0x08, 0x08, 0x08], // -
+    [0x00, 0x60, 0x60, 0x00, 0x00], // .
+    [0x20, 0x10, 0x08, 0x04, 0x02], // /
+    [0x3e, 0x51, 0x49, 0x45, 0x3e], // 0
+    [0x00, 0x42, 0x7f, 0x40, 0x00], // 1
+    [0x42, 0x61, 0x51, 0x49, 0x46], // 2
+    [0x21, 0x41, 0x45, 0x4b, 0x31], // 3
+    [0x18, 0x14, 0x12, 0x7f, 0x10], // 4
+    [0x27, 0x45, 0x45, 0x45, 0x39], // 5
+    [0x3c, 0x4a, 0x49, 0x49, 0x30], // 6
+    [0x01, 0x71, 0x09, 0x05, 0x03], // 7
+    [0x36, 0x49, 0x49, 0x49, 0x36], // 8
+    [0x06, 0x49, 0x49, 0x29, 0x1e], // 9
+    [0x00, 0x36, 0x36, 0x00, 0x00], // :
+    [0x00, 0x56, 0x36, 0x00, 0x00], // ;
+    [0x08, 0x14, 0x22, 0x41, 0x00], // <
+    [0x14, 0x14, 0x14, 0x14, 0x14], // =
+    [0x00, 0x41, 0x22, 0x14, 0x08], // >
+    [0x02, 0x01, 0x51, 0x09, 0x06], // ?
+    [0x32, 0x49, 0x79, 0x41, 0x3e], // @
+    [0x7e, 0x11, 0x11, 0x11, 0x7e], // A
+    [0x7f, 0x49, 0x49, 0x49, 0x36], // B
+    [0x3e, 0x41, 0x41, 0x41, 0x22], // C
+    [0x7f, 0x41, 0x41, 0x22, 0x1c], // D
+    [0x7f, 0x49, 0x49, 0x49, 0x41], // E
+    [0x7f, 0x09, 0x09, 0x09, 0x01], // F
+    [0x3e, 0x41, 0x49, 0x49, 0x7a], // G
+    [0x7f, 0x08, 0x08, 0x08, 0x7f], // H
+    [0x00, 0x41, 0x7f, 0x41, 0x00], // I
+    [0x20, 0x40, 0x41, 0x3f, 0x01], // J
+    [0x7f, 0x08, 0x14, 0x22, 0x41], // K
+    [0x7f, 0x40, 0x40, 0x40, 0x40], // L
+    [0x7f, 0x02, 0x0c, 0x02, 0x7f], // M
+    [0x7f, 0x04, 0x08, 0x10, 0x7f], // N
+    [0x3e, 0x41, 0x41, 0x41, 0x3e], // O
+    [0x7f, 0x09, 0x09, 0x09, 0x06], // P
+    [0x3e, 0x41, 0x51, 0x21, 0x5e], // Q
+    [0x7f, 0x09, 0x19, 0x29, 0x46], // R
+    [0x46, 0x49, 0x49, 0x49, 0x31], // S
+    [0x01, 0x01, 0x7f, 0x01, 0x01], // T
+    [0x3f, 0x40, 0x40, 0x40, 0x3f], // U
+    [0x1f, 0x20, 0x40, 0x20, 0x1f], // V
+    [0x3f, 0x40, 0x38, 0x40, 0x3f], // W
+    [0x63, 0x14, 0x08, 0x14, 0x63], // X
+    [0x07, 0x08, 0x70, 0x08, 0x07], // Y
+    [0x61, 0x51, 0x49, 0x45, 0x43], // Z
+    [0x00, 0x7f, 0x41, 0x41, 0x00], // [
+    [0x02, 0x04, 0x08, 0x10, 0x20], // \
+    [0x00, 0x41, 0x41, 0x7f, 0x00], // ]
+    [0x04, 0x02, 0x01, 0x02, 0x04], // ^
+    [0x40, 0x40, 0x40, 0x40, 0x40], // _
+    [0x00, 0x01, 0x02, 0x04, 0x00], // `
+    [0x20, 0x54, 0x54, 0x54, 0x78], // a
+    [0x7f, 0x48, 0x44, 0x44, 0x38], // b
+    [0x38, 0x44, 0x44, 0x44, 0x20], // c
+    [0x38, 0x44, 0x44, 0x48, 0x7f], // d
+    [0x38, 0x54, 0x54, 0x54, 0x18], // e
+    [0x08, 0x7e, 0x09, 0x01, 0x02], // f
+    [0x0c, 0x52, 0x52, 0x52, 0x3e], // g
+    [0x7f, 0x08, 0x04, 0x04, 0x78], // h
+    [0x00, 0x44, 0x7d, 0x40, 0x00], // i
+    [0x20, 0x40, 0x44, 0x3d, 0x00], // j
+    [0x7f, 0x10, 0x28, 0x44, 0x00], // k
+    [0x00, 0x41, 0x7f, 0x40, 0x00], // l
+    [0x7c, 0x04, 0x18, 0x04, 0x78], // m
+    [0x7c, 0x08, 0x04, 0x04, 0x78], // n
+    [0x38, 0x44, 0x44, 0x44, 0x38], // o
+    [0x7c, 0x14, 0x14, 0x14, 0x08], // p
+    [0x08, 0x14, 0x14, 0x18, 0x7c], // q
+    [0x7c, 0x08, 0x04, 0x04, 0x08], // r
+    [0x48, 0x54, 0x54, 0x54, 0x20], // s
+    [0x04, 0x3f, 0x44, 0x40, 0x20], // t
+    [0x3c, 0x40, 0x40, 0x20, 0x7c], // u
+    [0x1c, 0x20, 0x40, 0x20, 0x1c], // v
+    [0x3c, 0x40, 0x30, 0x40, 0x3c], // w
+    [0x44, 0x28, 0x10, 0x28, 0x44], // x
+    [0x0c, 0x50, 0x50, 0x50, 0x3c], // y
+    [0x44, 0x64, 0x54, 0x4c, 0x44], // z
+    [0x00, 0x08, 0x36, 0x41, 0x00], // {
+    [0x00, 0x00, 0x7f, 0x00, 0x00], // |
+    [0x00, 0x41, 0x36, 0x08, 0x00], // }
+    [0x08, 0x04, 0x08, 0x10, 0x08], // ~
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Params {
+    /// Text to show, characters outside of printable ASCII are shown as `?`.
+    pub text: String,
+    /// Scroll speed in columns per second, the text stands still at the left edge if 0.
+    pub speed: f32,
+    /// Color of the text, the current color is used if unset.
+    pub color: Option<Color>,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            text: "Hello".to_owned(),
+            speed: 10.0,
+            color: None,
+        }
+    }
+}
+
+const fn glyph(c: char) -> [u8; GLYPH_WIDTH] {
+    let index = match c {
+        ' '..='~' => c as usize - ' ' as usize,
+        _ => '?' as usize - ' ' as usize,
+    };
+
+    FONT[index]
+}
+
+pub fn render(ctx: &Context, params: &Params, hue: f32, sat: f32, val: f32, frame: &mut [Pixel]) {
+    let lit = {
+        let (r, g, b) = color_or_current(params.color, hue, sat, val);
+        Pixel::RGB { r, g, b }
+    };
+
+    let glyphs: Vec<_> = params.text.chars().map(glyph).collect();
+    let width = ctx.matrix.width;
+    let columns = glyphs.len() * ADVANCE;
+
+    // the text enters at the right edge and leaves completely at the left one before repeating
+    let period = columns + width;
+    let offset = if params.speed > 0.0 && period > 0 {
+        (ctx.elapsed.as_secs_f32() * params.speed) as usize % period
+    } else {
+        width
+    };
+
+    // center the text vertically on matrices taller than the font
+    let top = ctx.matrix.height.saturating_sub(GLYPH_HEIGHT) / 2;
+
+    for (i, led) in frame.iter_mut().enumerate() {
+        let (x, y) = ctx.matrix.position(i);
+
+        let lit_here = (x + offset)
+            .checked_sub(width)
+            .filter(|column| column % ADVANCE < GLYPH_WIDTH)
+            .and_then(|column| {
+                glyphs
+                    .get(column / ADVANCE)
+                    .map(|glyph| glyph[column % ADVANCE])
+            })
+            .zip(y.checked_sub(top).filter(|row| *row < GLYPH_HEIGHT))
+            .is_some_and(|(bits, row)| bits & (1 << row) != 0);
+
+        *led = if lit_here { lit } else { Pixel::OFF };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effects::context;
+    use crate::layout::Matrix;
+
+    #[test]
+    fn standing_text_starts_at_the_left_edge() {
+        let matrix = Matrix {
+            width: 6,
+            height: 7,
+        };
+        let params = Params {
+            text: "I".to_owned(),
+            speed: 0.0,
+            color: Some(Color {
+                r: 1.0,
+                g: 0.0,
+                b: 0.0,
+            }),
+        };
+        let mut frame = vec![Pixel::OFF; 42];
+
+        render(&context(0.0, matrix), &params, 0.0, 0.0, 0.5, &mut frame);
+
+        // the middle column of the I is lit from top to bottom
+        assert_eq!(frame[3 * 6 + 2].to_rgb(), (0.5, 0.0, 0.0));
+        assert_eq!(frame[0].to_rgb(), (0.0, 0.0, 0.0));
+        assert_eq!(frame[6 + 1].to_rgb(), (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn empty_text_on_an_empty_strip_renders_nothing() {
+        let params = Params {
+            text: String::new(),
+            ..Params::default()
+        };
+
+        render(
+            &context(1.0, Matrix::row(0)),
+            &params,
+            0.0,
+            1.0,
+            1.0,
+            &mut [],
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use serde::{Deserialize, Serialize};
+
+use crate::effects::{lerp, Context, PixelMemory};
+use crate::pixel::Pixel;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Params {
+    /// Chance per second of every LED to start twinkling.
+    pub density: f32,
+    /// Fade speed in full fades per second.
+    pub speed: f32,
+    /// Brightness of LEDs that aren't twinkling relative to the current value [0-1].
+    pub base: f32,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            density: 0.1,
+            speed: 1.0,
+            base: 0.2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Cell {
+    pub level: f32,
+    pub rising: bool,
+}
+
+impl Cell {
+    /// Starts fading in or out at random and advances the current fade.
+    pub fn advance(&mut self, ctx: &Context, density: f32, speed: f32, fade_in: bool) {
+        let dt = ctx.delta.as_secs_f32();
+
+        if self.level <= 0.0 && !self.rising && fastrand::f32() < density * dt {
+            if fade_in {
+                self.rising = true;
+            } else {
+                self.level = 1.0;
+            }
+        }
+
+        if self.rising {
+            self.level += speed * dt;
+
+            if self.level >= 1.0 {
+                self.level = 1.0;
+                self.rising = false;
+            }
+        } else {
+            self.level = speed.mul_add(-dt, self.level).max(0.0);
+        }
+    }
+}
+
+pub fn render(
+    ctx: &Context,
+    params: &Params,
+    cells: &mut PixelMemory<Cell>,
+    hue: f32,
+    sat: f32,
+    val: f32,
+    frame: &mut [Pixel],
+) {
+    let base = params.base.clamp(0.0, 1.0);
+
+    for (cell, led) in cells.cells(frame.len()).iter_mut().zip(frame) {
+        cell.advance(ctx, params.density, params.speed, true);
+
+        *led = Pixel::HSV {
+            h: hue,
+            s: sat,
+            v: val * lerp(base, 1.0, cell.level),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::effects::context;
+    use crate::layout::Matrix;
+
+    fn ctx(delta: f32) -> Context {
+        Context {
+            delta: Duration::from_secs_f32(delta),
+            ..context(0.0, Matrix::row(4))
+        }
+    }
+
+    #[test]
+    fn cells_fade_in_then_out() {
+        let mut cell = Cell {
+            level: 0.5,
+            rising: true,
+        };
+
+        cell.advance(&ctx(0.25), 0.0, 1.0, true);
+        assert!((cell.level - 0.75).abs() < 1e-6 && cell.rising);
+
+        cell.advance(&ctx(0.5), 0.0, 1.0, true);
+        assert!((cell.level - 1.0).abs() < 1e-6 && !cell.rising);
+
+        cell.advance(&ctx(0.25), 0.0, 1.0, true);
+        assert!((cell.level - 0.75).abs() < 1e-6);
+
+        cell.advance(&ctx(1.0), 0.0, 1.0, true);
+        assert!(cell.level.abs() < 1e-6);
+    }
+
+    #[test]
+    fn cells_start_with_full_density() {
+        let mut twinkle = Cell::default();
+        twinkle.advance(&ctx(1.0), 2.0, 0.5, true);
+        assert!((twinkle.level - 0.5).abs() < 1e-6 && twinkle.rising);
+
+        // sparkles jump to full brightness and fade out right away
+        let mut sparkle = Cell::default();
+        sparkle.advance(&ctx(0.25), 8.0, 1.0, false);
+        assert!((sparkle.level - 0.75).abs() < 1e-6 && !sparkle.rising);
+    }
+
+    #[test]
+    fn idle_leds_show_the_base() {
+        let params = Params {
+            density: 0.0,
+            base: 0.5,
+            ..Params::default()
+        };
+        let mut cells = PixelMemory::default();
+        let mut frame = [Pixel::OFF; 4];
+
+        render(&ctx(0.1), &params, &mut cells, 0.0, 0.0, 0.8, &mut frame);
+
+        for led in frame {
+            let (r, g, b) = led.to_rgb();
+            assert!((r - 0.4).abs() < 1e-6 && (g - 0.4).abs() < 1e-6 && (b - 0.4).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn memory_resets_on_a_new_count() {
+        let mut cells = PixelMemory::<Cell>::default();
+        cells.cells(3)[1].level = 1.0;
+
+        assert!((cells.cells(3)[1].level - 1.0).abs() < 1e-6);
+        assert_eq!(cells.cells(5).len(), 5);
+        assert!(cells.cells(5).iter().all(|cell| cell.level.abs() < 1e-6));
+    }
+}
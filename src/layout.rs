@@ -0,0 +1,385 @@
+use std::error::Error;
+use std::fmt;
+
+use serde::Deserialize;
+
+use crate::pixel::Pixel;
+
+/// A run of physical LEDs that is appended to the logical strip effects render to.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Segment {
+    /// First physical LED of the segment, continues after the previous segment if unset.
+    #[serde(default)]
+    pub start: Option<usize>,
+    pub count: usize,
+    /// Runs from the last LED of the segment to the first one.
+    #[serde(default)]
+    pub reverse: bool,
+    /// Length of the rows of serpentine wiring, every other row runs backwards.
+    #[serde(default)]
+    pub serpentine: Option<usize>,
+    /// Logical pixels without LEDs before the segment, e.g. for corners.
+    #[serde(default)]
+    pub skip: usize,
+}
+
+/// How the rows of a matrix are wired.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Wiring {
+    /// Every other row runs backwards.
+    #[default]
+    Serpentine,
+    /// All rows run in the same direction.
+    RowMajor,
+}
+
+/// Size of the grid effects render to, a strip is a single row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Matrix {
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Matrix {
+    pub const fn row(count: usize) -> Self {
+        Self {
+            width: count,
+            height: 1,
+        }
+    }
+
+    /// Returns the column and row of a pixel.
+    pub const fn position(self, i: usize) -> (usize, usize) {
+        if self.width == 0 {
+            return (0, 0);
+        }
+
+        (i % self.width, i / self.width)
+    }
+}
+
+#[derive(Debug)]
+pub enum LayoutError {
+    Invalid(String),
+    OutOfRange(usize),
+    Overlap(usize),
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Invalid(reason) => write!(fmt, "Invalid layout: {reason}"),
+            Self::OutOfRange(led) => write!(fmt, "Invalid layout: LED {led} doesn't exist"),
+            Self::Overlap(led) => write!(fmt, "Invalid layout: LED {led} is used twice"),
+        }
+    }
+}
+
+impl Error for LayoutError {}
+
+/// Maps the physical LEDs of a strip onto the pixels effects render to.
+#[derive(Debug, Clone)]
+pub struct Layout {
+    /// Logical pixel shown by each physical LED, unused LEDs stay off.
+    leds: Vec<Option<usize>>,
+    count: usize,
+    matrix: Matrix,
+}
+
+impl Layout {
+    /// Shows every pixel on the LED with the same index.
+    pub fn linear(count: usize) -> Self {
+        Self {
+            leds: (0..count).map(Some).collect(),
+            count,
+            matrix: Matrix::row(count),
+        }
+    }
+
+    /// Builds the layout of `leds` physical LEDs from segments, without segments it is linear.
+    pub fn new(segments: &[Segment], leds: usize) -> Result<Self, LayoutError> {
+        if segments.is_empty() {
+            return Ok(Self::linear(leds));
+        }
+
+        let mut layout = Self {
+            leds: vec![None; leds],
+            count: 0,
+            matrix: Matrix::row(0),
+        };
+        let mut next = 0;
+        let mut skipped = 0usize;
+
+        for segment in segments {
+            if segment.count == 0 {
+                return Err(LayoutError::Invalid(
+                    "segments need at least one LED".to_owned(),
+                ));
+            }
+            if segment.serpentine == Some(0) {
+                return Err(LayoutError::Invalid(
+                    "serpentine rows can't be empty".to_owned(),
+                ));
+            }
+
+            // every segment uses at least one LED, so this also bounds the logical count
+            skipped = skipped.saturating_add(segment.skip);
+            if skipped > leds {
+                return Err(LayoutError::Invalid(
+                    "more pixels are skipped than there are LEDs".to_owned(),
+                ));
+            }
+
+            let start = segment.start.unwrap_or(next);
+            layout.count += segment.skip;
+
+            for i in 0..segment.count {
+                let led = start
+                    .checked_add(segment.offset(i))
+                    .ok_or(LayoutError::OutOfRange(start))?;
+                let pixel = layout
+                    .leds
+                    .get_mut(led)
+                    .ok_or(LayoutError::OutOfRange(led))?;
+
+                if pixel.is_some() {
+                    return Err(LayoutError::Overlap(led));
+                }
+
+                *pixel = Some(layout.count + i);
+            }
+
+            // the segment fit, so neither of these can overflow
+            layout.count += segment.count;
+            next = start + segment.count;
+        }
+
+        layout.matrix = Matrix::row(layout.count);
+
+        Ok(layout)
+    }
+
+    /// Builds the layout of a matrix starting at the first of `leds` physical LEDs.
+    pub fn grid(matrix: Matrix, wiring: Wiring, leds: usize) -> Result<Self, LayoutError> {
+        if matrix.width == 0 || matrix.height == 0 {
+            return Err(LayoutError::Invalid(
+                "a matrix needs at least one row and column".to_owned(),
+            ));
+        }
+
+        let count = matrix
+            .width
+            .checked_mul(matrix.height)
+            .ok_or_else(|| LayoutError::Invalid("the matrix is too large".to_owned()))?;
+
+        let segment = Segment {
+            start: None,
+            count,
+            reverse: false,
+            serpentine: (wiring == Wiring::Serpentine).then_some(matrix.width),
+            skip: 0,
+        };
+
+        let mut layout = Self::new(&[segment], leds)?;
+        layout.matrix = matrix;
+
+        Ok(layout)
+    }
+
+    /// Returns the number of pixels effects render to.
+    pub const fn count(&self) -> usize {
+        self.count
+    }
+
+    pub const fn matrix(&self) -> Matrix {
+        self.matrix
+    }
+
+    /// Returns the pixel shown by a physical LED.
+    pub fn pixel(&self, frame: &[Pixel], led: usize) -> Pixel {
+        self.leds
+            .get(led)
+            .copied()
+            .flatten()
+            .and_then(|pixel| frame.get(pixel).copied())
+            .unwrap_or(Pixel::OFF)
+    }
+}
+
+impl Segment {
+    /// Returns the LED of the `i`-th pixel relative to the start of the segment.
+    fn offset(&self, i: usize) -> usize {
+        let i = if self.reverse { self.count - 1 - i } else { i };
+
+        let Some(row) = self.serpentine else {
+            return i;
+        };
+
+        let first = i - i % row;
+        let len = row.min(self.count - first);
+
+        if (i / row) % 2 == 1 {
+            first + len - 1 - (i - first)
+        } else {
+            i
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(start: Option<usize>, count: usize) -> Segment {
+        Segment {
+            start,
+            count,
+            reverse: false,
+            serpentine: None,
+            skip: 0,
+        }
+    }
+
+    fn pixels(segments: &[Segment], leds: usize) -> Result<Vec<Option<usize>>, LayoutError> {
+        Layout::new(segments, leds).map(|layout| layout.leds)
+    }
+
+    #[test]
+    fn without_segments_every_led_shows_its_pixel() -> Result<(), LayoutError> {
+        let layout = Layout::new(&[], 3)?;
+
+        assert_eq!(layout.leds, [Some(0), Some(1), Some(2)]);
+        assert_eq!(layout.count(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn segments_continue_after_each_other() -> Result<(), LayoutError> {
+        let segments = [segment(None, 2), segment(Some(4), 2)];
+
+        assert_eq!(
+            pixels(&segments, 6)?,
+            [Some(0), Some(1), None, None, Some(2), Some(3)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reverse_runs_from_the_last_led() -> Result<(), LayoutError> {
+        let segments = [
+            segment(None, 2),
+            Segment {
+                reverse: true,
+                ..segment(None, 3)
+            },
+        ];
+
+        assert_eq!(
+            pixels(&segments, 5)?,
+            [Some(0), Some(1), Some(4), Some(3), Some(2)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn skip_leaves_pixels_without_leds() -> Result<(), LayoutError> {
+        let segments = [
+            segment(None, 2),
+            Segment {
+                skip: 2,
+                ..segment(None, 2)
+            },
+        ];
+        let layout = Layout::new(&segments, 4)?;
+
+        assert_eq!(layout.leds, [Some(0), Some(1), Some(4), Some(5)]);
+        assert_eq!(layout.count(), 6);
+        Ok(())
+    }
+
+    #[test]
+    fn serpentine_reverses_every_other_row() -> Result<(), LayoutError> {
+        let segments = [Segment {
+            serpentine: Some(3),
+            ..segment(None, 8)
+        }];
+
+        assert_eq!(
+            pixels(&segments, 8)?,
+            [
+                Some(0),
+                Some(1),
+                Some(2),
+                Some(5),
+                Some(4),
+                Some(3),
+                Some(6),
+                Some(7)
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn grid_maps_rows_onto_the_wiring() -> Result<(), LayoutError> {
+        let matrix = Matrix {
+            width: 2,
+            height: 2,
+        };
+
+        let serpentine = Layout::grid(matrix, Wiring::Serpentine, 4)?;
+        assert_eq!(serpentine.leds, [Some(0), Some(1), Some(3), Some(2)]);
+        assert_eq!(serpentine.matrix().position(3), (1, 1));
+
+        let row_major = Layout::grid(matrix, Wiring::RowMajor, 4)?;
+        assert_eq!(row_major.leds, [Some(0), Some(1), Some(2), Some(3)]);
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_layouts_are_rejected() {
+        assert!(matches!(
+            pixels(&[segment(Some(3), 2)], 4),
+            Err(LayoutError::OutOfRange(4))
+        ));
+        assert!(matches!(
+            pixels(&[segment(None, 3), segment(Some(2), 1)], 4),
+            Err(LayoutError::Overlap(2))
+        ));
+        assert!(matches!(
+            pixels(&[segment(None, 0)], 4),
+            Err(LayoutError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn large_values_are_rejected_instead_of_overflowing() {
+        assert!(matches!(
+            pixels(&[segment(Some(usize::MAX), 2)], 4),
+            Err(LayoutError::OutOfRange(_))
+        ));
+        assert!(matches!(
+            pixels(
+                &[Segment {
+                    skip: usize::MAX,
+                    ..segment(None, 1)
+                }],
+                4
+            ),
+            Err(LayoutError::Invalid(_))
+        ));
+        assert!(matches!(
+            Layout::grid(
+                Matrix {
+                    width: usize::MAX,
+                    height: 2,
+                },
+                Wiring::RowMajor,
+                4
+            ),
+            Err(LayoutError::Invalid(_))
+        ));
+    }
+}
@@ -92,7 +92,7 @@ fn max_target_width(target: &str) -> usize {
     }
 }
 
-fn colored_level(style: &mut Style, level: Level) -> StyledValue<&'static str> {
+fn colored_level(style: &mut Style, level: Level) -> StyledValue<'_, &'static str> {
     match level {
         Level::Trace => style.set_color(Color::Magenta).value("TRACE"),
         Level::Debug => style.set_color(Color::Blue).value("DEBUG"),
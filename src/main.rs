@@ -9,47 +9,66 @@
     clippy::cast_possible_truncation,
     clippy::cast_sign_loss,
     clippy::cast_lossless,
+    clippy::cast_precision_loss,
     clippy::module_name_repetitions
 )]
 
-use std::io::{Error, ErrorKind};
-use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::io::Error;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::Instant;
 
 use clap::Parser;
 use error_stack::{IntoReport, ResultExt};
 use tokio::runtime::Runtime;
 
+use crate::effects::script::Runner;
+use crate::persistence::Startup;
+use crate::storage::Storage;
+
 #[macro_use]
 extern crate log;
 
 mod api;
+mod config;
 mod controller;
+mod effects;
+mod layout;
 mod logging;
+mod persistence;
 mod pixel;
+mod playlist;
+mod power;
+mod preset;
 mod state;
+mod storage;
 
 #[derive(Parser)]
+#[allow(clippy::doc_markdown)]
 #[clap(author, version, about, long_about = None)]
 struct Cli {
-    /// Sets the port to listen on
-    #[clap(short, long, value_parser, default_value_t = 88)]
-    port: u16,
+    /// Sets the config file (TOML) to load, command line options override its values
+    #[clap(long, value_parser)]
+    config: Option<PathBuf>,
+
+    /// Sets the port to listen on [default: 88]
+    #[clap(short, long, value_parser)]
+    port: Option<u16>,
 
-    /// Sets the ip address to listen on
-    #[clap(short, long, value_parser, default_value_t = IpAddr::V4(Ipv4Addr::new(0,0,0,0)))]
-    address: IpAddr,
+    /// Sets the ip address to listen on [default: 0.0.0.0]
+    #[clap(short, long, value_parser)]
+    address: Option<IpAddr>,
 
     /// Sets the pin to which the WS281x LED string is connected
     #[clap(short = 'P', long, value_parser)]
-    pin: i32,
+    pin: Option<i32>,
 
     /// Sets the count of LEDs in the string
     #[clap(short, long, value_parser)]
-    count: i32,
+    count: Option<i32>,
 
     /// Sets the used logging level
     /// Possible values: error, warn, info, debug, trace
@@ -57,26 +76,61 @@ struct Cli {
     /// Note: the LOG_LEVEL environment variable overrides this option
     #[clap(long, value_parser, verbatim_doc_comment)]
     log_level: Option<log::Level>,
+
+    /// Sets the directory to load scripts (*.rhai) from
+    #[clap(short, long, value_parser)]
+    scripts: Option<PathBuf>,
+
+    /// Sets the time in milliseconds a script may take to render one frame [default: 20]
+    #[clap(long, value_parser)]
+    script_budget: Option<u64>,
+
+    /// Sets the directory to store data like sequences and presets in [default: data]
+    #[clap(short, long, value_parser)]
+    data: Option<PathBuf>,
+
+    /// Sets what to show after starting
+    /// Possible values: restore (the state before the last shutdown), off or the name of a preset
+    /// [default: restore]
+    #[clap(long, value_parser, verbatim_doc_comment)]
+    startup: Option<Startup>,
 }
 
+#[allow(clippy::too_many_lines)]
 fn main() -> Result<(), Error> {
-    let cli = Cli::parse();
+    let config = match config::load(Cli::parse()) {
+        Ok(config) => config,
+        Err(report) => {
+            eprintln!("{report:?}");
+            return Err(Error::other("config error"));
+        }
+    };
 
-    let logger = cli.log_level.as_ref().map_or_else(
+    let logger = config.log_level.as_ref().map_or_else(
         || logging::init("Off"),
         |level| logging::init(level.as_str()),
     );
 
     match logger {
-        Ok(_) => {}
+        Ok(()) => {}
         Err(report) => {
             eprintln!("{report:?}");
-            return Err(Error::new(ErrorKind::Other, "logging setup error"));
+            return Err(Error::other("logging setup error"));
         }
     }
 
-    // state storage
-    let state = state::init();
+    // one state per logical strip
+    let states: Vec<_> = config
+        .counts()
+        .into_iter()
+        .map(|count| {
+            state::init(
+                count,
+                Storage::new(config.data.clone()),
+                config.scripts.clone(),
+            )
+        })
+        .collect();
 
     let rt = Runtime::new()
         .into_report()
@@ -86,29 +140,36 @@ fn main() -> Result<(), Error> {
         Ok(runtime) => runtime,
         Err(report) => {
             error!("{report:?}");
-            return Err(Error::new(ErrorKind::Other, "runtime error"));
+            return Err(Error::other("runtime error"));
         }
     };
 
-    let stop_api = match api::run(
-        Arc::clone(&state),
-        SocketAddr::new(cli.address, cli.port),
-        &rt,
-    ) {
+    for (state, name) in states.iter().zip(persistence::NAMES) {
+        {
+            let mut safe_state = rt.block_on(state.lock());
+            config.defaults.apply(&mut safe_state);
+            persistence::startup(&mut safe_state, &config.startup, name);
+        }
+        persistence::run(Arc::clone(state), name, &rt);
+    }
+
+    let stop_api = match api::run(&states, SocketAddr::new(config.address, config.port), &rt) {
         Ok(tx) => tx,
         Err(report) => {
             error!("{report:?}");
-            return Err(Error::new(ErrorKind::Other, "api server error"));
+            return Err(Error::other("api server error"));
         }
     };
 
-    let mut controller = match controller::init(cli.pin, cli.count) {
+    let scripts = Runner::new(config.scripts.clone(), config.script_budget);
+
+    let mut controller = match controller::init(&config, scripts) {
         Ok(data) => data,
         Err(report) => {
             //TODO: check if and how we could handle this result
             _ = stop_api.send(());
             error!("{report:?}");
-            return Err(Error::new(ErrorKind::Other, "controller error"));
+            return Err(Error::other("controller error"));
         }
     };
 
@@ -119,27 +180,43 @@ fn main() -> Result<(), Error> {
 
     info!("Running");
 
+    let mut next_frame = Instant::now();
+
     while !term.load(Ordering::Relaxed) {
         {
-            let safe_state = rt.block_on(state.lock());
+            let mut safe_states: Vec<_> = states
+                .iter()
+                .map(|state| rt.block_on(state.lock()))
+                .collect();
 
-            match controller.update(safe_state) {
-                Ok(_) => {}
+            match controller.update(&mut safe_states) {
+                Ok(()) => {}
                 Err(report) => {
                     warn!("{report:?}");
                 }
             }
         }
 
-        thread::sleep(Duration::from_millis(10));
+        // keep a steady frame rate no matter how long the update took
+        next_frame += config.loop_interval;
+        let now = Instant::now();
+        if next_frame > now {
+            thread::sleep(next_frame - now);
+        } else {
+            next_frame = now;
+        }
     }
 
     //TODO: check if and how we could handle this result
     _ = stop_api.send(());
 
+    for (state, name) in states.iter().zip(persistence::NAMES) {
+        persistence::save(&rt.block_on(state.lock()), name);
+    }
+
     //turn all LEDs off
     match controller.off() {
-        Ok(_) => {}
+        Ok(()) => {}
         Err(report) => {
             warn!("{report:?}");
         }
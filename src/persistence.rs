@@ -0,0 +1,204 @@
+use std::convert::Infallible;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tokio::runtime::Runtime;
+
+use crate::playlist::{Player, Playlist};
+use crate::preset::Preset;
+use crate::state::{Mode, State, StateStruct};
+use crate::storage::{PLAYLISTS, PRESETS, STATE};
+
+/// Names of the files the last states of the logical strips are stored in.
+pub const NAMES: [&str; 2] = ["last", "second"];
+
+/// Time the state has to stay unchanged before it gets written.
+const SAVE_DELAY: Duration = Duration::from_secs(5);
+
+/// What to show after starting the program.
+#[derive(Debug, Clone)]
+pub enum Startup {
+    /// Restore the state from before the last shutdown.
+    Restore,
+    Off,
+    /// Recall the preset with this name.
+    Preset(String),
+}
+
+impl FromStr for Startup {
+    type Err = Infallible;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Ok(match value {
+            "restore" => Self::Restore,
+            "off" => Self::Off,
+            name => Self::Preset(name.to_owned()),
+        })
+    }
+}
+
+impl fmt::Display for Startup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Restore => write!(f, "restore"),
+            Self::Off => write!(f, "off"),
+            Self::Preset(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    #[serde(flatten)]
+    preset: Preset,
+    /// Name of the running playlist.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    playlist: Option<String>,
+}
+
+impl Snapshot {
+    fn capture(state: &StateStruct) -> Self {
+        let mut preset = Preset::capture(state);
+
+        // neither the frames sent to DIRECT nor a half finished SLEEP can be resumed
+        preset.mode = match state.mode {
+            Mode::DIRECT => state.previous_mode,
+            Mode::SLEEP => state.params.sleep.end_mode,
+            mode => mode,
+        };
+
+        Self {
+            preset,
+            playlist: state.playlist.as_ref().map(|player| player.name.clone()),
+        }
+    }
+}
+
+/// Applies the startup policy to the fresh state.
+pub fn startup(state: &mut StateStruct, policy: &Startup, name: &str) {
+    match policy {
+        Startup::Off => {}
+        Startup::Preset(preset) => match state.storage.load::<Preset>(PRESETS, preset) {
+            Ok(preset) => preset.load(&state.storage).apply(state),
+            Err(report) => warn!("{report:?}"),
+        },
+        Startup::Restore => {
+            let snapshot = match state.storage.load::<Snapshot>(STATE, name) {
+                Ok(snapshot) => snapshot,
+                Err(report) => {
+                    info!("no state restored: {}", report.current_context());
+                    return;
+                }
+            };
+
+            snapshot.preset.load(&state.storage).apply(state);
+
+            if let Some(playlist) = snapshot.playlist {
+                match state.storage.load::<Playlist>(PLAYLISTS, &playlist) {
+                    Ok(loaded) => {
+                        let player = Player::new(playlist, loaded, &state.storage);
+                        state.play_playlist(player);
+                    }
+                    Err(report) => warn!("{report:?}"),
+                }
+            }
+        }
+    }
+}
+
+/// Writes the state to disk once it stopped changing for a moment.
+pub fn run(state: State, name: &'static str, runtime: &Runtime) {
+    runtime.spawn(async move {
+        let mut saved = None;
+        let mut changed: Option<(String, Instant)> = None;
+        let mut interval = tokio::time::interval(Duration::from_millis(500));
+
+        loop {
+            interval.tick().await;
+
+            let safe_state = state.lock().await;
+            let snapshot = Snapshot::capture(&safe_state);
+            let storage = safe_state.storage.clone();
+            drop(safe_state);
+
+            let key = match serde_json::to_string(&snapshot) {
+                Ok(key) => key,
+                Err(err) => {
+                    warn!("unable to serialize state: {err}");
+                    continue;
+                }
+            };
+
+            // the state right after startup doesn't need to be saved
+            let Some(last) = &saved else {
+                saved = Some(key);
+                continue;
+            };
+
+            if *last == key {
+                changed = None;
+                continue;
+            }
+
+            // restart the delay whenever the state changes again
+            match &changed {
+                Some((pending, since)) if *pending == key && since.elapsed() >= SAVE_DELAY => {}
+                Some((pending, _)) if *pending == key => continue,
+                _ => {
+                    changed = Some((key, Instant::now()));
+                    continue;
+                }
+            }
+
+            match storage.save(STATE, name, &snapshot) {
+                Ok(()) => trace!("saved state"),
+                Err(report) => warn!("{report:?}"),
+            }
+
+            saved = Some(key);
+            changed = None;
+        }
+    });
+}
+
+/// Writes the state to disk right away, e.g. when shutting down.
+pub fn save(state: &StateStruct, name: &str) {
+    if let Err(report) = state.storage.save(STATE, name, &Snapshot::capture(state)) {
+        warn!("{report:?}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use error_stack::{IntoReport, Result, ResultExt};
+    use serde_json::json;
+
+    use super::*;
+    use crate::state;
+    use crate::storage::{Storage, StorageError, TempDir};
+
+    #[test]
+    fn invalid_snapshots_are_not_restored() -> Result<(), StorageError> {
+        let dir = TempDir::new("persistence-restore")
+            .into_report()
+            .change_context(StorageError::Io)?;
+        let storage = Storage::new(dir.path().to_owned());
+        let snapshot = |params| json!({"mode": "FIRE", "hue": 400.0, "sat": 1.0, "val": 1.0, "interval": 1.0, "params": params});
+        let state = state::init(1, storage.clone(), None);
+        let mut state = state.blocking_lock();
+
+        storage.save(STATE, "last", &snapshot(json!({"fire": {"heat": 1}})))?;
+        startup(&mut state, &Startup::Restore, "last");
+        assert_eq!(state.mode, Mode::OFF);
+
+        storage.save(STATE, "last", &snapshot(json!({"fire": {"cooling": 80}})))?;
+        startup(&mut state, &Startup::Restore, "last");
+        assert_eq!(state.mode, Mode::FIRE);
+        assert!((state.hue - 40.0).abs() < 1e-4);
+        drop(state);
+
+        Ok(())
+    }
+}
@@ -1,12 +1,30 @@
-use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
 pub type RawColor = [u8; 4];
 
 #[allow(dead_code)]
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum Pixel {
-    RGB { r: f32, g: f32, b: f32 },
-    HSV { h: f32, s: f32, v: f32 },
+    RGB {
+        r: f32,
+        g: f32,
+        b: f32,
+    },
+    /// Color with a separate white channel for RGBW strips.
+    RGBW {
+        r: f32,
+        g: f32,
+        b: f32,
+        w: f32,
+    },
+    HSV {
+        h: f32,
+        s: f32,
+        v: f32,
+    },
     WHITE,
     GREEN,
     BLUE,
@@ -15,14 +33,14 @@ pub enum Pixel {
 }
 
 impl Pixel {
-    pub fn to_u8(&self) -> RawColor {
+    /// Returns the red, green and blue components in the range [0-1].
+    ///
+    /// The white component of `RGBW` pixels is left out, its color depends on the
+    /// white temperature of the strip and is only mixed in by `to_output`.
+    pub fn to_rgb(self) -> (f32, f32, f32) {
         match self {
-            Self::RGB { r, g, b } => {
-                let r_u: u8 = (r.clamp(0.0, 1.0) * 255.0) as u8;
-                let g_u: u8 = (g.clamp(0.0, 1.0) * 255.0) as u8;
-                let b_u: u8 = (b.clamp(0.0, 1.0) * 255.0) as u8;
-
-                [b_u, g_u, r_u, 0]
+            Self::RGB { r, g, b } | Self::RGBW { r, g, b, .. } => {
+                (r.clamp(0.0, 1.0), g.clamp(0.0, 1.0), b.clamp(0.0, 1.0))
             }
 
             Self::HSV { h, s, v } => {
@@ -66,22 +84,716 @@ impl Pixel {
                     b1 += x;
                 }
 
-                let r_u: u8 = (r1.clamp(0.0, 1.0) * 255.0) as u8;
-                let g_u: u8 = (g1.clamp(0.0, 1.0) * 255.0) as u8;
-                let b_u: u8 = (b1.clamp(0.0, 1.0) * 255.0) as u8;
+                (r1.clamp(0.0, 1.0), g1.clamp(0.0, 1.0), b1.clamp(0.0, 1.0))
+            }
+
+            Self::WHITE => (1.0, 1.0, 1.0),
+
+            Self::GREEN => (0.0, 1.0, 0.0),
+
+            Self::BLUE => (0.0, 0.0, 1.0),
+
+            Self::RED => (1.0, 0.0, 0.0),
+
+            Self::OFF => (0.0, 0.0, 0.0),
+        }
+    }
+
+    /// Returns the red, green, blue and white components in the range [0-1].
+    ///
+    /// Only `RGBW` pixels have a white component.
+    pub fn to_rgbw(self) -> (f32, f32, f32, f32) {
+        if let Self::RGBW { r, g, b, w } = self {
+            (
+                r.clamp(0.0, 1.0),
+                g.clamp(0.0, 1.0),
+                b.clamp(0.0, 1.0),
+                w.clamp(0.0, 1.0),
+            )
+        } else {
+            let (r, g, b) = self.to_rgb();
+            (r, g, b, 0.0)
+        }
+    }
+
+    /// Returns the values sent to the strip in the range [0-1] after white handling and gamma correction.
+    pub fn to_output(self, output: &Output) -> [f32; 4] {
+        let (r, g, b, w) = self.to_rgbw();
+
+        let (r, g, b, w) = if output.order.white() {
+            output.white.extract(r, g, b, w)
+        } else {
+            output.white.emulate(r, g, b, w)
+        };
+
+        output.gamma.apply(r, g, b, w)
+    }
+
+    pub fn to_u8(self, output: &Output) -> RawColor {
+        let [r, g, b, w] = self.to_output(output).map(|value| value * output.scale);
+
+        let r_u: u8 = (r * 255.0) as u8;
+        let g_u: u8 = (g * 255.0) as u8;
+        let b_u: u8 = (b * 255.0) as u8;
+        let w_u: u8 = (w * 255.0) as u8;
+
+        output.order.pack([r_u, g_u, b_u, w_u])
+    }
+
+    /// Like `to_u8`, but carries the part lost when rounding down over to the next frame.
+    ///
+    /// Over a few frames the LED then shows the exact value on average,
+    /// which hides the steps between the 8-bit values of slow and dark fades.
+    pub fn to_u8_dithered(self, output: &Output, residual: &mut [f32; 4]) -> RawColor {
+        let mut raw = [0; 4];
+
+        for ((raw, value), residual) in raw
+            .iter_mut()
+            .zip(self.to_output(output))
+            .zip(residual.iter_mut())
+        {
+            let target = (value * output.scale).mul_add(255.0, *residual);
+            let quantized = target.floor().clamp(0.0, 255.0);
 
-                [b_u, g_u, r_u, 0]
+            *residual = (target - quantized).clamp(0.0, 1.0);
+            *raw = quantized as u8;
+        }
+
+        output.order.pack(raw)
+    }
+}
+
+/// Settings of the strip used to turn pixels into raw colors.
+#[derive(Debug, Clone, Copy)]
+pub struct Output {
+    pub order: ColorOrder,
+    pub white: White,
+    pub gamma: Gamma,
+    /// Brightness factor set by the power limiter.
+    pub scale: f32,
+}
+
+/// Gamma correction of every channel, `1.0` sends the values unchanged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Gamma {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub w: f32,
+}
+
+impl Default for Gamma {
+    fn default() -> Self {
+        Self::all(1.0)
+    }
+}
+
+impl Gamma {
+    pub const fn all(gamma: f32) -> Self {
+        Self {
+            r: gamma,
+            g: gamma,
+            b: gamma,
+            w: gamma,
+        }
+    }
+
+    pub const fn channels(self) -> [f32; 4] {
+        [self.r, self.g, self.b, self.w]
+    }
+
+    /// Corrects the values of all channels in the range [0-1].
+    fn apply(self, r: f32, g: f32, b: f32, w: f32) -> [f32; 4] {
+        [
+            correct(r, self.r),
+            correct(g, self.g),
+            correct(b, self.b),
+            correct(w, self.w),
+        ]
+    }
+}
+
+fn correct(value: f32, gamma: f32) -> f32 {
+    // skip the costly power for the common case of no correction
+    if (gamma - 1.0).abs() < f32::EPSILON {
+        value
+    } else {
+        value.powf(gamma)
+    }
+}
+
+impl<'de> Deserialize<'de> for Gamma {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged, deny_unknown_fields)]
+        enum Repr {
+            // one value for all channels or a table with a value per channel
+            All(f32),
+            Channels {
+                r: Option<f32>,
+                g: Option<f32>,
+                b: Option<f32>,
+                w: Option<f32>,
+            },
+        }
+
+        Ok(match Repr::deserialize(deserializer)? {
+            Repr::All(gamma) => Self::all(gamma),
+            Repr::Channels { r, g, b, w } => Self {
+                r: r.unwrap_or(1.0),
+                g: g.unwrap_or(1.0),
+                b: b.unwrap_or(1.0),
+                w: w.unwrap_or(1.0),
+            },
+        })
+    }
+}
+
+/// How the white channel of RGBW strips is derived from RGB colors.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WhiteExtraction {
+    /// Only light the white LEDs for colors that set white explicitly.
+    Off,
+    /// Move the common part of red, green and blue to the white LEDs.
+    #[default]
+    Min,
+    /// Like `Min`, but take the color temperature of the white LEDs into account.
+    Accurate,
+}
+
+/// White LEDs of RGBW strips and how to use them.
+#[derive(Debug, Clone, Copy)]
+pub struct White {
+    extraction: WhiteExtraction,
+    /// Color of the white LEDs in the range [0-1], brightest channel at 1.
+    color: (f32, f32, f32),
+}
+
+impl White {
+    pub fn new(extraction: WhiteExtraction, temperature: f32) -> Self {
+        Self {
+            extraction,
+            color: kelvin_to_rgb(temperature),
+        }
+    }
+
+    /// Moves the white part of a color to the white channel.
+    fn extract(&self, r: f32, g: f32, b: f32, w: f32) -> (f32, f32, f32, f32) {
+        let (wr, wg, wb) = match self.extraction {
+            WhiteExtraction::Off => return (r, g, b, w),
+            WhiteExtraction::Min => (1.0, 1.0, 1.0),
+            WhiteExtraction::Accurate => self.color,
+        };
+
+        let white = (r / wr.max(f32::EPSILON))
+            .min(g / wg.max(f32::EPSILON))
+            .min(b / wb.max(f32::EPSILON))
+            .clamp(0.0, 1.0);
+
+        (
+            wr.mul_add(-white, r).max(0.0),
+            wg.mul_add(-white, g).max(0.0),
+            wb.mul_add(-white, b).max(0.0),
+            (w + white).min(1.0),
+        )
+    }
+
+    /// Mixes the white channel into the colors for strips without white LEDs.
+    const fn emulate(&self, r: f32, g: f32, b: f32, w: f32) -> (f32, f32, f32, f32) {
+        let (wr, wg, wb) = self.color;
+
+        (
+            wr.mul_add(w, r).min(1.0),
+            wg.mul_add(w, g).min(1.0),
+            wb.mul_add(w, b).min(1.0),
+            0.0,
+        )
+    }
+}
+
+/// Approximates the color of a black body with the given temperature in Kelvin.
+///
+/// Based on the curve fit by Tanner Helland, valid from 1000 K to 40000 K.
+fn kelvin_to_rgb(temperature: f32) -> (f32, f32, f32) {
+    let t = temperature.clamp(1000.0, 40000.0) / 100.0;
+
+    let r = if t <= 66.0 {
+        255.0
+    } else {
+        329.698_73 * (t - 60.0).powf(-0.133_204_76)
+    };
+
+    let g = if t <= 66.0 {
+        99.470_8f32.mul_add(t.ln(), -161.119_57)
+    } else {
+        288.122_17 * (t - 60.0).powf(-0.075_514_85)
+    };
+
+    let b = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.517_73f32.mul_add((t - 10.0).ln(), -305.044_8)
+    };
+
+    let (r, g, b) = (
+        r.clamp(0.0, 255.0) / 255.0,
+        g.clamp(0.0, 255.0) / 255.0,
+        b.clamp(0.0, 255.0) / 255.0,
+    );
+    let max = r.max(g).max(b);
+
+    (r / max, g / max, b / max)
+}
+
+/// Order in which a strip expects the color channels, e.g. `grb` or `grbw`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorOrder {
+    /// Index into `[r, g, b, w]` for every channel in the order they are sent.
+    channels: [usize; 4],
+    white: bool,
+}
+
+impl ColorOrder {
+    pub const GRB: Self = Self {
+        channels: [1, 0, 2, 3],
+        white: false,
+    };
+    pub const RGB: Self = Self {
+        channels: [0, 1, 2, 3],
+        white: false,
+    };
+    pub const GRBW: Self = Self {
+        channels: [1, 0, 2, 3],
+        white: true,
+    };
+
+    /// Returns whether the strip has a white channel.
+    pub const fn white(self) -> bool {
+        self.white
+    }
+
+    /// Arranges the `[r, g, b, w]` values for a strip that is driven as plain RGB(W).
+    ///
+    /// The ws281x library sends the bytes of a `RawColor` in the order 2, 1, 0 (, 3).
+    pub const fn pack(self, values: [u8; 4]) -> RawColor {
+        let [first, second, third, fourth] = self.channels;
+
+        [values[third], values[second], values[first], values[fourth]]
+    }
+}
+
+impl FromStr for ColorOrder {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut channels = [0, 1, 2, 3];
+        let mut seen = [false; 4];
+
+        for (i, c) in value.to_ascii_lowercase().chars().enumerate() {
+            let channel = match c {
+                'r' => 0,
+                'g' => 1,
+                'b' => 2,
+                'w' => 3,
+                _ => return Err(format!("invalid channel {c} in color order {value}")),
+            };
+
+            if i >= 4 || seen[channel] {
+                return Err(format!("invalid color order {value}"));
             }
 
-            Self::WHITE => [255, 255, 255, 0],
+            channels[i] = channel;
+            seen[channel] = true;
+        }
+
+        if !seen[0] || !seen[1] || !seen[2] {
+            return Err(format!(
+                "color order {value} needs each of r, g and b (and optionally w)"
+            ));
+        }
+
+        Ok(Self {
+            channels,
+            white: seen[3],
+        })
+    }
+}
+
+impl fmt::Display for ColorOrder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let len = if self.white { 4 } else { 3 };
+
+        for channel in &self.channels[..len] {
+            write!(f, "{}", ['r', 'g', 'b', 'w'][*channel])?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'de> Deserialize<'de> for ColorOrder {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
 
-            Self::GREEN => [255, 0, 0, 0],
+/// RGB color that is written as a hex string like `ff8800` in the API.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Color {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+}
+
+impl Color {
+    pub const fn to_pixel(self) -> Pixel {
+        Pixel::RGB {
+            r: self.r,
+            g: self.g,
+            b: self.b,
+        }
+    }
+
+    /// Converts the color into the perceptual Oklab color space.
+    ///
+    /// The LEDs emit light linearly to the channel values, so no transfer function is applied.
+    pub fn to_oklab(self) -> [f32; 3] {
+        let l = 0.051_445_993_f32
+            .mul_add(
+                self.b,
+                0.412_221_46_f32.mul_add(self.r, 0.536_332_55 * self.g),
+            )
+            .cbrt();
+        let m = 0.107_396_96_f32
+            .mul_add(
+                self.b,
+                0.211_903_5_f32.mul_add(self.r, 0.680_699_5 * self.g),
+            )
+            .cbrt();
+        let s = 0.629_978_7_f32
+            .mul_add(
+                self.b,
+                0.088_302_46_f32.mul_add(self.r, 0.281_718_85 * self.g),
+            )
+            .cbrt();
+
+        [
+            (-0.004_072_047_f32).mul_add(s, 0.210_454_26_f32.mul_add(l, 0.793_617_8 * m)),
+            0.450_593_7_f32.mul_add(s, 1.977_998_5_f32.mul_add(l, -2.428_592_2 * m)),
+            (-0.808_675_77_f32).mul_add(s, 0.025_904_037_f32.mul_add(l, 0.782_771_77 * m)),
+        ]
+    }
+
+    pub fn from_oklab([l, a, b]: [f32; 3]) -> Self {
+        let l_ = 0.215_803_76_f32
+            .mul_add(b, 0.396_337_78_f32.mul_add(a, l))
+            .powi(3);
+        let m_ = (-0.063_854_17_f32)
+            .mul_add(b, (-0.105_561_346_f32).mul_add(a, l))
+            .powi(3);
+        let s_ = (-1.291_485_5_f32)
+            .mul_add(b, (-0.089_484_18_f32).mul_add(a, l))
+            .powi(3);
+
+        Self {
+            r: 0.230_969_94_f32
+                .mul_add(s_, 4.076_741_7_f32.mul_add(l_, -3.307_711_6 * m_))
+                .clamp(0.0, 1.0),
+            g: (-0.341_319_38_f32)
+                .mul_add(s_, (-1.268_438_f32).mul_add(l_, 2.609_757_4 * m_))
+                .clamp(0.0, 1.0),
+            b: 1.707_614_7_f32
+                .mul_add(s_, (-0.004_196_086_3_f32).mul_add(l_, -0.703_418_6 * m_))
+                .clamp(0.0, 1.0),
+        }
+    }
+}
 
-            Self::BLUE => [0, 255, 0, 0],
+impl FromStr for Color {
+    type Err = String;
 
-            Self::RED => [0, 0, 255, 0],
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let hex = value.trim().trim_start_matches('#');
 
-            Self::OFF => [0, 0, 0, 0],
+        if hex.len() != 6 || !hex.is_ascii() {
+            return Err(format!("invalid color: {value}"));
         }
+
+        let channel = |i: usize| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .map(|c| c as f32 / 255.0)
+                .map_err(|_| format!("invalid color: {value}"))
+        };
+
+        Ok(Self {
+            r: channel(0)?,
+            g: channel(2)?,
+            b: channel(4)?,
+        })
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:02x}{:02x}{:02x}",
+            (self.r.clamp(0.0, 1.0) * 255.0).round() as u8,
+            (self.g.clamp(0.0, 1.0) * 255.0).round() as u8,
+            (self.b.clamp(0.0, 1.0) * 255.0).round() as u8
+        )
+    }
+}
+
+impl Serialize for Color {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            // hex colors made up of digits only look like numbers when coming from a query
+            Int(u32),
+            Hex(String),
+        }
+
+        match Repr::deserialize(deserializer)? {
+            Repr::Int(value) => format!("{value:06}").parse(),
+            Repr::Hex(hex) => hex.parse(),
+        }
+        .map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(order: ColorOrder, white: White) -> Output {
+        Output {
+            order,
+            white,
+            gamma: Gamma::default(),
+            scale: 1.0,
+        }
+    }
+
+    fn assert_close(actual: [f32; 4], expected: [f32; 4]) {
+        assert!(
+            actual
+                .iter()
+                .zip(expected)
+                .all(|(actual, expected)| (actual - expected).abs() < 1e-4),
+            "{actual:?} != {expected:?}"
+        );
+    }
+
+    #[test]
+    fn white_is_mixed_in_the_white_temperature() {
+        let white = Pixel::RGBW {
+            r: 0.0,
+            g: 0.0,
+            b: 0.0,
+            w: 1.0,
+        };
+        let warm = White::new(WhiteExtraction::Min, 2700.0);
+        let (r, g, b) = kelvin_to_rgb(2700.0);
+
+        assert_eq!(white.to_rgb(), (0.0, 0.0, 0.0));
+        assert_close(
+            white.to_output(&output(ColorOrder::RGB, warm)),
+            [r, g, b, 0.0],
+        );
+        assert!(r > g && g > b);
+    }
+
+    #[test]
+    fn white_is_extracted_on_rgbw_strips() {
+        let pixel = Pixel::RGB {
+            r: 1.0,
+            g: 0.6,
+            b: 0.6,
+        };
+
+        let min = White::new(WhiteExtraction::Min, 4500.0);
+        assert_close(
+            pixel.to_output(&output(ColorOrder::GRBW, min)),
+            [0.4, 0.0, 0.0, 0.6],
+        );
+
+        let off = White::new(WhiteExtraction::Off, 4500.0);
+        assert_close(
+            pixel.to_output(&output(ColorOrder::GRBW, off)),
+            [1.0, 0.6, 0.6, 0.0],
+        );
+    }
+
+    #[test]
+    fn gamma_corrects_every_channel() {
+        let pixel = Pixel::RGBW {
+            r: 0.5,
+            g: 0.5,
+            b: 0.5,
+            w: 0.5,
+        };
+        let mut output = output(ColorOrder::GRBW, White::new(WhiteExtraction::Off, 4500.0));
+        output.gamma = Gamma {
+            r: 1.0,
+            g: 2.0,
+            b: 3.0,
+            w: 0.5,
+        };
+
+        assert_close(pixel.to_output(&output), [0.5, 0.25, 0.125, 0.5f32.sqrt()]);
+    }
+
+    #[test]
+    fn gamma_is_one_value_or_a_table() -> Result<(), toml::de::Error> {
+        #[derive(Deserialize)]
+        struct File {
+            gamma: Gamma,
+        }
+
+        let all: File = toml::from_str("gamma = 2.2")?;
+        assert_eq!(all.gamma, Gamma::all(2.2));
+
+        let table: File = toml::from_str("gamma = { r = 2.0, b = 2.5 }")?;
+        assert_close(table.gamma.channels(), [2.0, 1.0, 2.5, 1.0]);
+
+        assert!(toml::from_str::<File>("gamma = { red = 2.0 }").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn dithering_shows_the_exact_value_on_average() {
+        let value = 10.25 / 255.0;
+        let pixel = Pixel::RGB {
+            r: value,
+            g: value,
+            b: value,
+        };
+        let output = output(ColorOrder::RGB, White::new(WhiteExtraction::Min, 4500.0));
+        let mut residual = [0.0; 4];
+
+        let frames: Vec<_> = (0..100)
+            .map(|_| pixel.to_u8_dithered(&output, &mut residual)[0])
+            .collect();
+        let total: u32 = frames.iter().map(|&raw| u32::from(raw)).sum();
+
+        assert!(frames.iter().all(|&raw| raw == 10 || raw == 11));
+        assert!((1024..=1026).contains(&total), "{total}");
+        assert_eq!(pixel.to_u8(&output)[0], 10);
+    }
+
+    #[test]
+    fn dithering_keeps_full_and_zero_values() {
+        let output = output(ColorOrder::RGB, White::new(WhiteExtraction::Min, 4500.0));
+        let mut residual = [0.0; 4];
+
+        for _ in 0..10 {
+            assert_eq!(
+                Pixel::RED.to_u8_dithered(&output, &mut residual),
+                [0, 0, 255, 0]
+            );
+        }
+    }
+
+    #[test]
+    fn colors_are_hex_strings() -> Result<(), String> {
+        let color: Color = "#ff8000".parse()?;
+
+        assert_eq!(color.to_string(), "ff8000");
+        assert!((color.g - 128.0 / 255.0).abs() < f32::EPSILON);
+        assert_eq!(
+            " 00ff00 ".parse::<Color>()?.to_pixel().to_rgb(),
+            (0.0, 1.0, 0.0)
+        );
+
+        assert!("ff80".parse::<Color>().is_err());
+        assert!("gg0000".parse::<Color>().is_err());
+        assert!("ff00öö".parse::<Color>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn oklab_round_trips() {
+        for color in [
+            Color {
+                r: 1.0,
+                g: 0.0,
+                b: 0.0,
+            },
+            Color {
+                r: 0.2,
+                g: 0.5,
+                b: 0.9,
+            },
+            Color {
+                r: 1.0,
+                g: 1.0,
+                b: 1.0,
+            },
+        ] {
+            let back = Color::from_oklab(color.to_oklab());
+
+            assert_close(
+                [back.r, back.g, back.b, 0.0],
+                [color.r, color.g, color.b, 0.0],
+            );
+        }
+
+        // white has full lightness and no hue
+        let [l, a, b] = Color {
+            r: 1.0,
+            g: 1.0,
+            b: 1.0,
+        }
+        .to_oklab();
+        assert!((l - 1.0).abs() < 1e-3 && a.abs() < 1e-3 && b.abs() < 1e-3);
+    }
+
+    #[test]
+    fn kelvin_to_rgb_is_normalized() {
+        let (r, g, b) = kelvin_to_rgb(6600.0);
+        assert!(r > 0.99 && g > 0.99 && b > 0.99);
+
+        let (r, g, b) = kelvin_to_rgb(1000.0);
+        assert!((r - 1.0).abs() < f32::EPSILON && g < 0.5 && b.abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn color_orders_are_parsed() -> Result<(), String> {
+        assert_eq!("GRB".parse::<ColorOrder>()?, ColorOrder::GRB);
+        assert_eq!("grbw".parse::<ColorOrder>()?, ColorOrder::GRBW);
+        assert_eq!("bgr".parse::<ColorOrder>()?.to_string(), "bgr");
+        assert!("wrgb".parse::<ColorOrder>()?.white());
+
+        assert!("rg".parse::<ColorOrder>().is_err());
+        assert!("rgbr".parse::<ColorOrder>().is_err());
+        assert!("rgbwx".parse::<ColorOrder>().is_err());
+        assert!("rgx".parse::<ColorOrder>().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn color_orders_pack_in_send_order() -> Result<(), String> {
+        let values = [1, 2, 3, 4];
+
+        // the library sends byte 2 first, then 1, 0 and 3
+        assert_eq!(ColorOrder::RGB.pack(values), [3, 2, 1, 4]);
+        assert_eq!(ColorOrder::GRB.pack(values), [3, 1, 2, 4]);
+        assert_eq!("wbgr".parse::<ColorOrder>()?.pack(values), [2, 3, 4, 1]);
+
+        Ok(())
     }
 }
@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use serde::{de, Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+use crate::effects::sequence::Sequence;
+use crate::effects::Params;
+use crate::preset::{Loaded, Preset};
+use crate::state::Mode;
+use crate::storage::{Storage, PRESETS, SEQUENCES};
+
+/// A mode shown for some time as part of a playlist.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Entry {
+    /// Mode to show, defaults to the mode of the preset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<Mode>,
+    /// Name of the preset to recall before applying the other fields.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
+    /// Seconds until the next entry is shown.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub duration: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hue: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sat: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub val: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub white: Option<f32>,
+    /// Effect parameters to change, grouped by effect like in `/params`.
+    #[serde(
+        default,
+        skip_serializing_if = "Value::is_null",
+        deserialize_with = "deserialize_params"
+    )]
+    pub params: Value,
+    /// Name of the sequence to load for the SEQUENCE mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Playlist {
+    #[serde(deserialize_with = "deserialize_entries")]
+    pub entries: Vec<Entry>,
+    /// Play the entries in a random order, shuffled again after every pass.
+    #[serde(default)]
+    pub shuffle: bool,
+    /// Seconds to blend from one entry into the next.
+    #[serde(default, deserialize_with = "deserialize_crossfade")]
+    pub crossfade: f32,
+}
+
+fn deserialize_duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    let duration = f32::deserialize(deserializer)?;
+
+    if !duration.is_finite() || duration <= 0.0 {
+        return Err(de::Error::custom("durations need to be positive"));
+    }
+
+    Ok(duration)
+}
+
+fn deserialize_crossfade<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    let crossfade = f32::deserialize(deserializer)?;
+
+    if !crossfade.is_finite() || crossfade < 0.0 {
+        return Err(de::Error::custom("crossfade can't be negative"));
+    }
+
+    Ok(crossfade)
+}
+
+// reject unknown effects and parameters right away instead of when the entry is shown
+fn deserialize_params<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Value, D::Error> {
+    let params = Value::deserialize(deserializer)?;
+
+    Params::default()
+        .merge(&params)
+        .map_err(de::Error::custom)?;
+
+    Ok(params)
+}
+
+fn deserialize_entries<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Entry>, D::Error> {
+    let entries = Vec::<Entry>::deserialize(deserializer)?;
+
+    if entries.is_empty() {
+        return Err(de::Error::custom("at least one entry is required"));
+    }
+
+    if entries
+        .iter()
+        .any(|entry| entry.mode.is_none() && entry.preset.is_none())
+    {
+        return Err(de::Error::custom("entries need a mode or a preset"));
+    }
+
+    Ok(entries)
+}
+
+/// Playback state of the running playlist.
+pub struct Player {
+    pub name: String,
+    pub playlist: Playlist,
+    order: Vec<usize>,
+    position: usize,
+    started: Instant,
+    /// Presets and sequences of the entries, loaded up front so switching entries
+    /// doesn't read from disk while the state is locked.
+    presets: HashMap<String, Loaded>,
+    sequences: HashMap<String, Sequence>,
+}
+
+impl Player {
+    /// Prepares a playlist and loads the presets and sequences of its entries.
+    ///
+    /// Entries whose preset or sequence can't be loaded are shown without it.
+    pub fn new(name: String, playlist: Playlist, storage: &Storage) -> Self {
+        let mut presets = HashMap::new();
+        let mut sequences = HashMap::new();
+
+        for entry in &playlist.entries {
+            if let Some(name) = &entry.preset {
+                if !presets.contains_key(name) {
+                    match storage.load::<Preset>(PRESETS, name) {
+                        Ok(preset) => {
+                            presets.insert(name.clone(), preset.load(storage));
+                        }
+                        Err(report) => warn!("{report:?}"),
+                    }
+                }
+            }
+
+            if let Some(name) = &entry.sequence {
+                if !sequences.contains_key(name) {
+                    match storage.load::<Sequence>(SEQUENCES, name) {
+                        Ok(sequence) => {
+                            sequences.insert(name.clone(), sequence);
+                        }
+                        Err(report) => warn!("{report:?}"),
+                    }
+                }
+            }
+        }
+
+        let mut player = Self {
+            order: (0..playlist.entries.len()).collect(),
+            name,
+            playlist,
+            position: 0,
+            started: Instant::now(),
+            presets,
+            sequences,
+        };
+
+        if player.playlist.shuffle {
+            fastrand::shuffle(&mut player.order);
+        }
+
+        player
+    }
+
+    /// Returns the index of the current entry in the playlist.
+    pub fn index(&self) -> usize {
+        self.order[self.position]
+    }
+
+    pub fn current(&self) -> &Entry {
+        &self.playlist.entries[self.index()]
+    }
+
+    pub fn preset(&self, name: &str) -> Option<&Loaded> {
+        self.presets.get(name)
+    }
+
+    pub fn sequence(&self, name: &str) -> Option<&Sequence> {
+        self.sequences.get(name)
+    }
+
+    /// Returns the time left until the next entry is shown.
+    pub fn remaining(&self) -> Duration {
+        Duration::try_from_secs_f32(self.current().duration)
+            .unwrap_or_default()
+            .saturating_sub(self.started.elapsed())
+    }
+
+    /// Moves on to the next entry.
+    pub fn advance(&mut self) {
+        self.position += 1;
+
+        if self.position >= self.order.len() {
+            self.position = 0;
+
+            if self.playlist.shuffle {
+                fastrand::shuffle(&mut self.order);
+            }
+        }
+
+        self.started = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn playlist(json: &str) -> Result<Playlist, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    #[test]
+    fn invalid_playlists_are_rejected() {
+        assert!(playlist(r#"{"entries": []}"#).is_err());
+        assert!(playlist(r#"{"entries": [{"duration": 1}]}"#).is_err());
+        assert!(playlist(r#"{"entries": [{"mode": "STATIC", "duration": 0}]}"#).is_err());
+        assert!(
+            playlist(r#"{"entries": [{"mode": "STATIC", "duration": 1}], "crossfade": -1}"#)
+                .is_err()
+        );
+        assert!(playlist(
+            r#"{"entries": [{"mode": "FIRE", "duration": 1, "params": {"fire": {"heat": 1}}}]}"#
+        )
+        .is_err());
+        assert!(playlist(r#"{"entries": [{"preset": "evening", "duration": 1}]}"#).is_ok());
+    }
+
+    #[test]
+    fn entries_play_in_order() -> Result<(), serde_json::Error> {
+        let playlist = playlist(
+            r#"{"entries": [
+                {"mode": "STATIC", "duration": 1},
+                {"mode": "RAINBOW", "duration": 2},
+                {"mode": "OFF", "duration": 3}
+            ]}"#,
+        )?;
+        let mut player = Player::new("test".to_owned(), playlist, &Storage::new(PathBuf::new()));
+
+        let mut indices = Vec::new();
+        for _ in 0..4 {
+            indices.push(player.index());
+            player.advance();
+        }
+
+        assert_eq!(indices, [0, 1, 2, 0]);
+        assert!(player.remaining() <= Duration::from_secs(2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn shuffle_shows_every_entry_per_pass() -> Result<(), serde_json::Error> {
+        let playlist = playlist(
+            r#"{"shuffle": true, "entries": [
+                {"mode": "STATIC", "duration": 1},
+                {"mode": "RAINBOW", "duration": 1},
+                {"mode": "OFF", "duration": 1},
+                {"mode": "SLEEP", "duration": 1}
+            ]}"#,
+        )?;
+        let mut player = Player::new("test".to_owned(), playlist, &Storage::new(PathBuf::new()));
+
+        for _ in 0..3 {
+            let mut pass: Vec<_> = (0..4)
+                .map(|_| {
+                    let index = player.index();
+                    player.advance();
+                    index
+                })
+                .collect();
+            pass.sort_unstable();
+
+            assert_eq!(pass, [0, 1, 2, 3]);
+        }
+
+        Ok(())
+    }
+}
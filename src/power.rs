@@ -0,0 +1,104 @@
+use serde::{Deserialize, Serialize};
+
+/// Current budget of the power supply feeding all strips.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[allow(clippy::struct_field_names)]
+pub struct Power {
+    /// Maximum current the power supply can deliver in mA.
+    pub max_current: f32,
+    /// Current of one color channel of an LED at full brightness in mA.
+    #[serde(default = "led_current")]
+    pub led_current: f32,
+    /// Current of an LED that is off in mA.
+    #[serde(default = "idle_current")]
+    pub idle_current: f32,
+}
+
+const fn led_current() -> f32 {
+    20.0
+}
+
+const fn idle_current() -> f32 {
+    1.0
+}
+
+/// Estimated draw of the last frame and the limit applied to it, shown by `/power`.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Status {
+    pub max_current: f32,
+    /// Current the frame would draw without the limit in mA.
+    pub requested: f32,
+    /// Current the frame draws as sent in mA.
+    pub current: f32,
+    /// Brightness factor applied to stay within the budget [0-1].
+    pub scale: f32,
+}
+
+impl Power {
+    /// Returns the current of `leds` LEDs that don't show anything.
+    pub fn idle(&self, leds: usize) -> f32 {
+        self.idle_current * leds as f32
+    }
+
+    /// Scales the brightness of a frame down so it stays within the budget.
+    ///
+    /// `load` is the sum of all channels of all LEDs in the range [0-1].
+    pub fn limit(&self, leds: usize, load: f32) -> Status {
+        let idle = self.idle(leds);
+        let lit = load * self.led_current;
+        let requested = idle + lit;
+
+        // the idle current can't be reduced, only the lit part gets scaled
+        let scale = if requested > self.max_current && lit > 0.0 {
+            ((self.max_current - idle) / lit).clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+
+        Status {
+            max_current: self.max_current,
+            requested,
+            current: lit.mul_add(scale, idle),
+            scale,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POWER: Power = Power {
+        max_current: 4000.0,
+        led_current: 20.0,
+        idle_current: 1.0,
+    };
+
+    #[test]
+    fn frames_within_the_budget_stay_unchanged() {
+        let status = POWER.limit(100, 50.0);
+
+        assert!((status.requested - 1100.0).abs() < 1e-3);
+        assert!((status.current - status.requested).abs() < 1e-3);
+        assert!((status.scale - 1.0).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn only_the_lit_part_is_scaled_down() {
+        // 300 LEDs at full white: 300 mA idle and 18000 mA lit
+        let status = POWER.limit(300, 900.0);
+
+        assert!((status.requested - 18300.0).abs() < 1e-3);
+        assert!((status.current - 4000.0).abs() < 1e-2);
+        assert!((status.scale - 3700.0 / 18000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn dark_frames_need_no_limit() {
+        let status = POWER.limit(5000, 0.0);
+
+        assert!((status.requested - 5000.0).abs() < 1e-3);
+        assert!((status.scale - 1.0).abs() < f32::EPSILON);
+    }
+}
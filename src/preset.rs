@@ -0,0 +1,202 @@
+use std::time::Duration;
+
+use serde::{de, Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+use crate::effects::custom::{Program, Source};
+use crate::effects::sequence::Sequence;
+use crate::effects::Params;
+use crate::state::{Mode, StateStruct};
+use crate::storage::{Storage, SEQUENCES};
+
+/// Snapshot of the state that can be saved under a name and recalled later.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Preset {
+    pub mode: Mode,
+    pub hue: f32,
+    pub sat: f32,
+    pub val: f32,
+    #[serde(default)]
+    pub white: f32,
+    /// Interval of the modes whose speed depends on the hue in seconds.
+    pub interval: f32,
+    #[serde(default, deserialize_with = "deserialize_params")]
+    pub params: Params,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub custom: Option<Source>,
+    /// Name of the sequence played in the SEQUENCE mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<String>,
+}
+
+impl Preset {
+    pub fn capture(state: &StateStruct) -> Self {
+        Self {
+            mode: state.mode,
+            hue: state.hue,
+            sat: state.sat,
+            val: state.val,
+            white: state.white,
+            interval: state.interval.as_secs_f32(),
+            params: state.params.clone(),
+            custom: state
+                .custom
+                .as_ref()
+                .map(|program| program.source().clone()),
+            sequence: state.sequence.as_ref().map(|(name, _)| name.clone()),
+        }
+    }
+
+    /// Returns the interval of the preset, `None` if it is shorter than the 1 ms the
+    /// controller works with.
+    pub fn interval(&self) -> Option<Duration> {
+        Duration::try_from_secs_f32(self.interval)
+            .ok()
+            .filter(|interval| interval.as_millis() > 0)
+    }
+
+    /// Loads the sequence of the preset so it can be applied without reading from disk.
+    pub fn load(self, storage: &Storage) -> Loaded {
+        let sequence = self.sequence.as_ref().and_then(|name| {
+            match storage.load::<Sequence>(SEQUENCES, name) {
+                Ok(sequence) => Some((name.clone(), sequence)),
+                Err(report) => {
+                    warn!("{report:?}");
+                    None
+                }
+            }
+        });
+
+        Loaded {
+            preset: self,
+            sequence,
+        }
+    }
+}
+
+// reject parameters effects can't handle like the API does
+fn deserialize_params<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Params, D::Error> {
+    let changes = Value::deserialize(deserializer)?;
+    let mut params = Params::default();
+
+    params.merge(&changes).map_err(de::Error::custom)?;
+
+    Ok(params)
+}
+
+/// A preset together with its sequence, ready to be applied while the state is locked.
+#[derive(Debug, Clone)]
+pub struct Loaded {
+    preset: Preset,
+    sequence: Option<(String, Sequence)>,
+}
+
+impl Loaded {
+    /// Restores the state and (re)starts the mode of the preset.
+    pub fn apply(self, state: &mut StateStruct) {
+        let mode = self.preset.mode;
+        let interval = self.restore(state);
+
+        state.set_mode(mode);
+
+        if let Some(interval) = interval {
+            state.interval = interval;
+        }
+    }
+
+    pub const fn mode(&self) -> Mode {
+        self.preset.mode
+    }
+
+    /// Restores the state of the preset without switching the mode.
+    ///
+    /// Returns the interval of the preset to use once its mode is set, `None` if it is invalid.
+    pub fn restore(self, state: &mut StateStruct) -> Option<Duration> {
+        let Self { preset, sequence } = self;
+        let interval = preset.interval();
+
+        if interval.is_none() {
+            warn!("ignoring invalid interval of preset: {} s", preset.interval);
+        }
+
+        // files on disk may have been edited by hand, limit them like the API does
+        state.hue = ((preset.hue % 360.0) + 360.0) % 360.0;
+        state.sat = preset.sat.clamp(0.0, 1.0);
+        state.val = preset.val.clamp(0.0, 1.0);
+        state.white = preset.white.clamp(0.0, 1.0);
+        state.params = preset.params;
+
+        if let Some(source) = preset.custom {
+            match Program::compile(source) {
+                Ok(program) => state.custom = Some(program),
+                Err(err) => warn!("unable to compile custom expressions of preset: {err}"),
+            }
+        }
+
+        if sequence.is_some() {
+            state.sequence = sequence;
+        }
+
+        interval
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preset(interval: f32) -> Preset {
+        Preset {
+            mode: Mode::STROBE,
+            hue: 0.0,
+            sat: 1.0,
+            val: 1.0,
+            white: 0.0,
+            interval,
+            params: Params::default(),
+            custom: None,
+            sequence: None,
+        }
+    }
+
+    #[test]
+    fn intervals_below_a_millisecond_are_invalid() {
+        let millis = |interval: f32| preset(interval).interval().map(|i| i.as_millis());
+
+        assert_eq!(millis(0.5), Some(500));
+        assert_eq!(millis(0.0015), Some(1));
+        assert_eq!(preset(0.0005).interval(), None);
+        assert_eq!(preset(0.0).interval(), None);
+        assert_eq!(preset(-1.0).interval(), None);
+    }
+
+    #[test]
+    fn invalid_params_are_rejected() {
+        let parse = |params: &str| {
+            serde_json::from_str::<Preset>(&format!(
+                r#"{{"mode": "FIRE", "hue": 0, "sat": 1, "val": 1, "interval": 1, "params": {params}}}"#
+            ))
+        };
+
+        assert!(parse(r#"{"fire": {"cooling": 80}}"#).is_ok());
+        assert!(parse(r#"{"fire": {"heat": 1}}"#).is_err());
+        assert!(parse(r#"{"sleep": {"end_mode": "SLEEP"}}"#).is_err());
+    }
+
+    #[test]
+    fn restored_values_are_limited() {
+        let state = crate::state::init(1, Storage::new(std::path::PathBuf::new()), None);
+        let mut state = state.blocking_lock();
+        let mut preset = preset(0.5);
+        preset.hue = -90.0;
+        preset.val = 2.0;
+        preset.white = -1.0;
+
+        preset.load(&state.storage).apply(&mut state);
+
+        assert!((state.hue - 270.0).abs() < 1e-4);
+        assert!((state.val - 1.0).abs() < f32::EPSILON && state.white.abs() < f32::EPSILON);
+        assert_eq!(state.interval, Duration::from_millis(500));
+        drop(state);
+    }
+}
@@ -1,13 +1,21 @@
+use std::collections::BTreeMap;
 use std::fmt;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize};
 use strum_macros::{EnumString, EnumVariantNames, FromRepr};
 use tokio::sync::Mutex;
 
+use crate::effects::{custom::Program, sequence::Sequence, Params};
+use crate::pixel::Color;
+use crate::playlist::{Entry, Player};
+use crate::power;
+use crate::storage::Storage;
+
 #[repr(u8)]
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, EnumVariantNames, FromRepr, EnumString)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, EnumVariantNames, FromRepr, EnumString)]
 #[strum(ascii_case_insensitive)]
 pub enum Mode {
     OFF = 0,
@@ -18,6 +26,23 @@ pub enum Mode {
     COLORRAPE = 5,
     STROBE = 6,
     IDENTIFY = 7,
+    SUNRISE = 8,
+    FIRE = 9,
+    TWINKLE = 10,
+    SPARKLE = 11,
+    CHASE = 12,
+    COMET = 13,
+    SCANNER = 14,
+    BREATHE = 15,
+    GRADIENT = 16,
+    PLASMA = 17,
+    LAVA = 18,
+    AURORA = 19,
+    CUSTOM = 20,
+    SCRIPT = 21,
+    DIRECT = 22,
+    SEQUENCE = 23,
+    TEXT = 24,
 }
 
 impl fmt::Display for Mode {
@@ -31,31 +56,318 @@ impl fmt::Display for Mode {
             Self::COLORRAPE => write!(f, "COLORRAPE"),
             Self::STROBE => write!(f, "STROBE"),
             Self::IDENTIFY => write!(f, "IDENTIFY"),
+            Self::SUNRISE => write!(f, "SUNRISE"),
+            Self::FIRE => write!(f, "FIRE"),
+            Self::TWINKLE => write!(f, "TWINKLE"),
+            Self::SPARKLE => write!(f, "SPARKLE"),
+            Self::CHASE => write!(f, "CHASE"),
+            Self::COMET => write!(f, "COMET"),
+            Self::SCANNER => write!(f, "SCANNER"),
+            Self::BREATHE => write!(f, "BREATHE"),
+            Self::GRADIENT => write!(f, "GRADIENT"),
+            Self::PLASMA => write!(f, "PLASMA"),
+            Self::LAVA => write!(f, "LAVA"),
+            Self::AURORA => write!(f, "AURORA"),
+            Self::CUSTOM => write!(f, "CUSTOM"),
+            Self::SCRIPT => write!(f, "SCRIPT"),
+            Self::DIRECT => write!(f, "DIRECT"),
+            Self::SEQUENCE => write!(f, "SEQUENCE"),
+            Self::TEXT => write!(f, "TEXT"),
+        }
+    }
+}
+
+// accept the same representations as the API: case-insensitive names or the integer value
+impl<'de> Deserialize<'de> for Mode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Int(u8),
+            Name(String),
+        }
+
+        match Repr::deserialize(deserializer)? {
+            Repr::Int(value) => {
+                Self::from_repr(value).ok_or_else(|| de::Error::custom("unknown mode"))
+            }
+            Repr::Name(name) => name.parse().map_err(|_| de::Error::custom("unknown mode")),
         }
     }
 }
 
 pub struct StateStruct {
+    /// Number of LEDs in the string.
+    pub count: usize,
     pub hue: f32,
     pub sat: f32,
     pub val: f32,
+    /// Brightness of the white LEDs in the STATIC mode.
+    pub white: f32,
     pub mode: Mode,
     pub interval: Duration,
     pub start: Instant,
     pub render: bool,
+    pub params: Params,
+    pub custom: Option<Program>,
+    /// Loaded scripts and their load errors, if any.
+    pub scripts: BTreeMap<String, Option<String>>,
+    pub reload_scripts: bool,
+    /// Directory scripts are loaded from and uploaded to.
+    pub script_dir: Option<PathBuf>,
+    /// Frame shown in the DIRECT mode.
+    pub direct: Vec<Color>,
+    /// Switch back to the previous mode once this deadline passes without a new frame.
+    pub direct_deadline: Option<Instant>,
+    pub previous_mode: Mode,
+    /// Name and frames of the sequence shown in the SEQUENCE mode.
+    pub sequence: Option<(String, Sequence)>,
+    pub storage: Storage,
+    pub playlist: Option<Player>,
+    /// Blend into the next frames for this long, taken by the controller.
+    pub fade: Option<Duration>,
+    /// Power limit applied to the last frame, set by the controller.
+    pub power: Option<power::Status>,
+}
+
+impl StateStruct {
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.start = Instant::now();
+        self.render = true;
+        self.direct_deadline = None;
+        self.update_interval();
+    }
+
+    // some modes derive their speed from the hue value
+    pub fn update_interval(&mut self) {
+        self.interval = match self.mode {
+            Mode::ALARM => Duration::from_secs(1),
+            Mode::COLORRAPE => Duration::from_millis(500 + (9500.0 * (self.hue / 360.0)) as u64),
+            Mode::STROBE => Duration::from_millis(50 + (950.0 * (self.hue / 360.0)) as u64),
+            _ => Duration::from_mins(5),
+        };
+    }
+
+    /// Writes `colors` into the DIRECT frame starting at LED `start` and switches to DIRECT,
+    /// stopping a running playlist.
+    ///
+    /// Returns the number of written LEDs, nothing changes if no LED was written.
+    pub fn write_direct(
+        &mut self,
+        start: usize,
+        colors: &[Color],
+        timeout: Option<Duration>,
+    ) -> usize {
+        let end = start.saturating_add(colors.len()).min(self.direct.len());
+        let written = end.saturating_sub(start);
+
+        if written == 0 {
+            return 0;
+        }
+
+        self.direct[start..end].copy_from_slice(&colors[..written]);
+
+        if self.mode != Mode::DIRECT {
+            self.previous_mode = self.mode;
+            self.playlist = None;
+            self.set_mode(Mode::DIRECT);
+        }
+
+        self.direct_deadline = timeout.map(|timeout| Instant::now() + timeout);
+        self.render = true;
+
+        written
+    }
+
+    /// Starts a playlist with its first entry.
+    pub fn play_playlist(&mut self, player: Player) {
+        let entry = player.current().clone();
+
+        self.playlist = Some(player);
+        self.apply_entry(&entry);
+    }
+
+    /// Switches to the next entry of the running playlist.
+    pub fn advance_playlist(&mut self) {
+        let Some(player) = &mut self.playlist else {
+            return;
+        };
+
+        player.advance();
+        let entry = player.current().clone();
+
+        self.apply_entry(&entry);
+    }
+
+    /// Switches to the next entry of the running playlist once the current one is over.
+    pub fn update_playlist(&mut self) {
+        if self
+            .playlist
+            .as_ref()
+            .is_some_and(|player| player.remaining().is_zero())
+        {
+            self.advance_playlist();
+        }
+    }
+
+    fn apply_entry(&mut self, entry: &Entry) {
+        let player = self.playlist.as_ref();
+        let preset = entry.preset.as_ref().and_then(|name| {
+            let preset = player?.preset(name).cloned();
+            if preset.is_none() {
+                warn!("preset {name} of playlist entry isn't loaded");
+            }
+            preset
+        });
+        let sequence = entry.sequence.as_ref().and_then(|name| {
+            let sequence = player?.sequence(name).cloned();
+            if sequence.is_none() {
+                warn!("sequence {name} of playlist entry isn't loaded");
+            }
+            Some((name.clone(), sequence?))
+        });
+
+        let mut mode = entry.mode;
+        let mut interval = None;
+
+        if let Some(preset) = preset {
+            let preset_mode = preset.mode();
+
+            // the interval of the preset only belongs to its own mode
+            interval = preset
+                .restore(self)
+                .filter(|_| entry.mode.is_none_or(|mode| mode == preset_mode));
+            mode = mode.or(Some(preset_mode));
+        }
+
+        if let Some(hue) = entry.hue {
+            self.hue = hue;
+        }
+        if let Some(sat) = entry.sat {
+            self.sat = sat;
+        }
+        if let Some(val) = entry.val {
+            self.val = val;
+        }
+        if let Some(white) = entry.white {
+            self.white = white;
+        }
+
+        if let Err(err) = self.params.merge(&entry.params) {
+            warn!("unable to apply playlist entry: {err}");
+        }
+
+        if sequence.is_some() {
+            self.sequence = sequence;
+        }
+
+        let crossfade = self
+            .playlist
+            .as_ref()
+            .map_or(0.0, |player| player.playlist.crossfade);
+        self.fade = Duration::try_from_secs_f32(crossfade)
+            .ok()
+            .filter(|fade| !fade.is_zero());
+
+        self.set_mode(mode.unwrap_or(self.mode));
+
+        if let Some(interval) = interval {
+            self.interval = interval;
+        }
+    }
+
+    /// Returns the time left until a timed mode finishes.
+    pub fn remaining(&self) -> Option<Duration> {
+        let duration = match self.mode {
+            Mode::SLEEP => self.params.sleep.duration,
+            Mode::SUNRISE => self.params.sunrise.duration,
+            _ => return None,
+        };
+
+        let duration = Duration::try_from_secs_f32(duration).unwrap_or_default();
+
+        Some(duration.saturating_sub(self.start.elapsed()))
+    }
 }
 
 pub type State = Arc<Mutex<StateStruct>>;
 
-pub fn init() -> State {
+pub fn init(count: usize, storage: Storage, script_dir: Option<PathBuf>) -> State {
     trace!("init state");
     Arc::new(Mutex::new(StateStruct {
+        count,
         hue: 0.0,
         sat: 1.0,
         val: 1.0,
+        white: 0.0,
         mode: Mode::OFF,
-        interval: Duration::from_millis(300_000),
+        interval: Duration::from_mins(5),
         start: Instant::now(),
         render: true,
+        params: Params::default(),
+        custom: None,
+        scripts: BTreeMap::new(),
+        reload_scripts: true,
+        script_dir,
+        direct: vec![
+            Color {
+                r: 0.0,
+                g: 0.0,
+                b: 0.0,
+            };
+            count
+        ],
+        direct_deadline: None,
+        previous_mode: Mode::OFF,
+        sequence: None,
+        storage,
+        playlist: None,
+        fade: None,
+        power: None,
     }))
 }
+
+#[cfg(test)]
+mod tests {
+    use error_stack::{IntoReport, Result, ResultExt};
+    use serde_json::json;
+
+    use super::*;
+    use crate::playlist::Playlist;
+    use crate::storage::{StorageError, TempDir, PRESETS};
+
+    #[test]
+    fn playlist_entries_keep_the_interval_of_their_preset() -> Result<(), StorageError> {
+        let dir = TempDir::new("state-playlist")
+            .into_report()
+            .change_context(StorageError::Io)?;
+        let storage = Storage::new(dir.path().to_owned());
+        storage.save(
+            PRESETS,
+            "strobe",
+            &json!({"mode": "STROBE", "hue": 0.0, "sat": 1.0, "val": 1.0, "interval": 0.5}),
+        )?;
+
+        let playlist: Playlist = serde_json::from_value(json!({"entries": [
+            {"preset": "strobe", "duration": 1},
+            {"preset": "strobe", "mode": "ALARM", "duration": 1}
+        ]}))
+        .into_report()
+        .change_context(StorageError::Io)?;
+        let state = init(1, storage.clone(), None);
+        let mut state = state.blocking_lock();
+
+        state.play_playlist(Player::new("test".to_owned(), playlist, &storage));
+        assert_eq!(state.mode, Mode::STROBE);
+        assert_eq!(state.interval, Duration::from_millis(500));
+
+        // a different mode keeps its own interval
+        state.advance_playlist();
+        assert_eq!(state.mode, Mode::ALARM);
+        assert_eq!(state.interval, Duration::from_secs(1));
+        drop(state);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,203 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use error_stack::{IntoReport, Report, Result, ResultExt};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Kind of the stored sequences.
+pub const SEQUENCES: &str = "sequences";
+/// Kind of the stored playlists.
+pub const PLAYLISTS: &str = "playlists";
+/// Kind of the stored presets.
+pub const PRESETS: &str = "presets";
+/// Kind of the saved state.
+pub const STATE: &str = "state";
+
+#[derive(Debug)]
+pub enum StorageError {
+    InvalidName,
+    NotFound,
+    Io,
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidName => fmt.write_str("Storage error: invalid name"),
+            Self::NotFound => fmt.write_str("Storage error: not found"),
+            Self::Io => fmt.write_str("Storage error: unable to access data"),
+        }
+    }
+}
+
+impl Error for StorageError {}
+
+/// JSON files stored by kind (e.g. `sequences`) and name in the data directory.
+#[derive(Debug, Clone)]
+pub struct Storage {
+    dir: PathBuf,
+}
+
+impl Storage {
+    pub const fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Checks that a name is usable as file name, also for files stored elsewhere like scripts.
+    pub fn check_name(name: &str) -> Result<(), StorageError> {
+        // only allow simple names so nothing outside of the directory can be touched
+        let valid = !name.is_empty()
+            && name.len() <= 64
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+        if !valid {
+            return Err(Report::new(StorageError::InvalidName)
+                .attach_printable(format!("invalid name: {name}")));
+        }
+
+        Ok(())
+    }
+
+    fn path(&self, kind: &str, name: &str) -> Result<PathBuf, StorageError> {
+        Self::check_name(name)?;
+
+        Ok(self.dir.join(kind).join(format!("{name}.json")))
+    }
+
+    pub fn list(&self, kind: &str) -> Result<Vec<String>, StorageError> {
+        let dir = self.dir.join(kind);
+
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut names: Vec<String> = fs::read_dir(&dir)
+            .into_report()
+            .attach_printable_lazy(|| format!("unable to read {}", dir.display()))
+            .change_context(StorageError::Io)?
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                (path.extension()? == "json")
+                    .then(|| path.file_stem().map(|s| s.to_string_lossy().into_owned()))?
+            })
+            .collect();
+
+        names.sort();
+
+        Ok(names)
+    }
+
+    pub fn exists(&self, kind: &str, name: &str) -> Result<bool, StorageError> {
+        Ok(self.path(kind, name)?.exists())
+    }
+
+    pub fn load<T: DeserializeOwned>(&self, kind: &str, name: &str) -> Result<T, StorageError> {
+        let path = self.path(kind, name)?;
+
+        if !path.exists() {
+            return Err(Report::new(StorageError::NotFound)
+                .attach_printable(format!("{} does not exist", path.display())));
+        }
+
+        let data = fs::read(&path)
+            .into_report()
+            .attach_printable_lazy(|| format!("unable to read {}", path.display()))
+            .change_context(StorageError::Io)?;
+
+        serde_json::from_slice(&data)
+            .into_report()
+            .attach_printable_lazy(|| format!("unable to parse {}", path.display()))
+            .change_context(StorageError::Io)
+    }
+
+    pub fn save<T: Serialize>(
+        &self,
+        kind: &str,
+        name: &str,
+        value: &T,
+    ) -> Result<(), StorageError> {
+        let path = self.path(kind, name)?;
+
+        let data = serde_json::to_vec(value)
+            .into_report()
+            .attach_printable_lazy(|| format!("unable to serialize {name}"))
+            .change_context(StorageError::Io)?;
+
+        fs::create_dir_all(self.dir.join(kind))
+            .into_report()
+            .attach_printable_lazy(|| format!("unable to create {}", self.dir.display()))
+            .change_context(StorageError::Io)?;
+
+        write(&path, &data)
+    }
+
+    pub fn delete(&self, kind: &str, name: &str) -> Result<(), StorageError> {
+        let path = self.path(kind, name)?;
+
+        if !path.exists() {
+            return Err(Report::new(StorageError::NotFound)
+                .attach_printable(format!("{} does not exist", path.display())));
+        }
+
+        fs::remove_file(&path)
+            .into_report()
+            .attach_printable_lazy(|| format!("unable to delete {}", path.display()))
+            .change_context(StorageError::Io)
+    }
+}
+
+/// Writes a file through a temporary file, so a crash never leaves a half written file behind.
+pub fn write(path: &Path, data: &[u8]) -> Result<(), StorageError> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+
+    fs::write(&tmp, data)
+        .and_then(|()| fs::rename(&tmp, path))
+        .into_report()
+        .attach_printable_lazy(|| format!("unable to write {}", path.display()))
+        .change_context(StorageError::Io)
+}
+
+/// Empty directory below the system temp directory, removed again when dropped.
+#[cfg(test)]
+pub struct TempDir {
+    path: PathBuf,
+}
+
+#[cfg(test)]
+impl TempDir {
+    /// Creates a directory that is unique per process and call.
+    pub fn new(name: &str) -> std::io::Result<Self> {
+        static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "lighting-manager-{name}-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+        ));
+
+        // left behind by an earlier run with the same process id
+        if path.exists() {
+            fs::remove_dir_all(&path)?;
+        }
+        fs::create_dir_all(&path)?;
+
+        Ok(Self { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        // at worst the directory is left behind
+        _ = fs::remove_dir_all(&self.path);
+    }
+}